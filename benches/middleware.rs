@@ -0,0 +1,70 @@
+//! Middleware chain benchmark, run with `cargo bench --bench middleware`
+//!
+//! Sends requests through an increasing number of middlewares and counts the allocations made
+//! per request. Middlewares are applied to the handlers once, so the count should stay flat
+//! as middlewares are added.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use haro::{Application, DynHandler, Request, Response};
+
+const ITERATIONS: usize = 100_000;
+
+/// Allocator counting the allocations made by the process
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn noop(next: DynHandler) -> DynHandler {
+    Arc::new(move |req: Request| next(req))
+}
+
+fn build_app(num_middlewares: usize) -> Application {
+    let mut app = Application::new("0:8080");
+    for _ in 0..num_middlewares {
+        app.middleware(noop);
+    }
+    app.get("/users/<id:int>", |_| Response::str("user"));
+    app
+}
+
+/// Average time and allocations of a request
+fn bench(app: &Application, path: &str) -> (Duration, usize) {
+    // the middlewares are applied on the first request
+    black_box(app.request("GET", path, HashMap::new(), &[]));
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(app.request("GET", black_box(path), HashMap::new(), &[]));
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    (elapsed, allocations / ITERATIONS)
+}
+
+fn main() {
+    println!("{:>12} {:>12} {:>12}", "middlewares", "time", "allocations");
+    for num_middlewares in [0, 1, 10, 100] {
+        let app = build_app(num_middlewares);
+        let (time, allocations) = bench(&app, "/users/42");
+        println!("{num_middlewares:>12} {time:>12?} {allocations:>12}");
+    }
+}
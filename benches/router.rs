@@ -0,0 +1,41 @@
+//! Route lookup benchmark, run with `cargo bench --bench router`
+//!
+//! Registers an increasing number of routes and measures how long it takes to dispatch a
+//! request to the last one, the cost should stay flat as the route count grows.
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use haro::{Application, Response};
+
+const ITERATIONS: u32 = 100_000;
+
+fn build_app(num_routes: usize) -> Application {
+    let mut app = Application::new("0:8080");
+    for i in 0..num_routes / 2 {
+        app.get(&format!("/static/{i}/items"), |_| Response::str("static"));
+        app.get(&format!("/api/v{i}/users/<id:int>/posts"), |_| {
+            Response::str("param")
+        });
+    }
+    app
+}
+
+fn bench(app: &Application, path: &str) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(app.request("GET", black_box(path), HashMap::new(), &[]));
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    println!("{:>8} {:>12} {:>12}", "routes", "static", "param");
+    for num_routes in [10, 100, 1_000, 10_000] {
+        let app = build_app(num_routes);
+        let last = num_routes / 2 - 1;
+        let static_time = bench(&app, &format!("/static/{last}/items"));
+        let param_time = bench(&app, &format!("/api/v{last}/users/42/posts"));
+        println!("{num_routes:>8} {static_time:>12?} {param_time:>12?}");
+    }
+}
@@ -1,23 +1,116 @@
 use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream};
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
 use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
-use std::thread::available_parallelism;
+use std::thread::{self, available_parallelism};
+use std::time::Duration;
 
-use log::{debug, info};
+use http::header::{CONNECTION, RETRY_AFTER};
+use http::{Method, StatusCode, Version};
+use log::{debug, error, info, warn};
+use once_cell::sync::OnceCell;
 
-use crate::http::conn::Conn;
-use crate::middleware::Middleware;
+use crate::http::conn::{Conn, Timeouts};
+use crate::http::request::{Limits, ParseError};
+use crate::middleware::{Cors, Middleware};
 use crate::pool::ThreadPool;
-use crate::router::Router;
-use crate::{DynHandler, Handler, Request, Response};
+use crate::router::{handler, Route, Router, UrlError, Urls};
+use crate::shutdown::{ServerHandle, ShutdownHandle};
+use crate::{Blueprint, DynHandler, Handler, IntoResponse, Request, Response};
+
+/// Longest time spent writing the response to a connection rejected when the queue is full
+const REJECT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connection settings shared with every worker
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    keep_alive: Duration,
+    limits: Limits,
+    timeouts: Timeouts,
+}
+
+/// Handler building the response sent when a handler or middleware panicked, from the panic
+/// message
+type PanicHandler = Arc<dyn Fn(&str) -> Response + Send + Sync>;
+type BeforeHook = Arc<dyn Fn(&mut Request) -> Option<Response> + Send + Sync>;
+type AfterHook = Arc<dyn Fn(Response) -> Response + Send + Sync>;
+type TeardownHook = Arc<dyn Fn(Option<&str>) + Send + Sync>;
+type ErrorHandler = Arc<dyn Fn(Response) -> Response + Send + Sync>;
+
+/// Functions run around every request, shared with every worker
+#[derive(Clone)]
+struct Hooks {
+    before: Vec<BeforeHook>,
+    after: Vec<AfterHook>,
+    teardown: Vec<TeardownHook>,
+    errors: Vec<(StatusCode, ErrorHandler)>,
+    panic: PanicHandler,
+    cors: Option<Cors>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            before: Vec::new(),
+            after: Vec::new(),
+            teardown: Vec::new(),
+            errors: Vec::new(),
+            panic: Arc::new(internal_server_error),
+            cors: None,
+        }
+    }
+}
+
+impl Hooks {
+    /// Rewrite `res` with the error handler registered for its status
+    fn on_error(&self, res: Response) -> Response {
+        match self
+            .errors
+            .iter()
+            .find(|(status, _)| *status == res.status())
+        {
+            Some((_, handler)) => handler(res),
+            None => res,
+        }
+    }
+
+    /// Rewrite `res` with its error handler then run the after hooks on it, returns the panic
+    /// message if a hook panicked, the response is then the one of the panic handler
+    fn finish(&self, res: Response, context: &str) -> (Response, Option<String>) {
+        let result = catch(|| {
+            let res = self.on_error(res);
+            self.after.iter().fold(res, |res, hook| hook(res))
+        });
+        match result {
+            Ok(res) => (res, None),
+            Err(message) => {
+                error!("panic while handling {context}: {message}");
+                (self.on_error((self.panic)(&message)), Some(message))
+            }
+        }
+    }
+}
 
 /// A web Application with routes and middlewares
 pub struct Application {
-    addr: &'static str,
+    addr: String,
     num_threads: usize,
+    config: Config,
     router: Router,
+    urls: Arc<Urls>,
     middlewares: Vec<Middleware>,
+    /// Router with the middlewares applied, built on the first request after a change
+    compiled: OnceCell<Arc<Router>>,
+    hooks: Arc<Hooks>,
+    shutdown: ShutdownHandle,
+    shutdown_timeout: Duration,
+    shutdown_hooks: Vec<Box<dyn Fn() + Send + Sync>>,
+    queue_capacity: usize,
+    /// `Retry-After` sent to the connections rejected when the queue is full, `None` waits for
+    /// room in the queue instead
+    retry_after: Option<Duration>,
 }
 
 impl Application {
@@ -28,17 +121,30 @@ impl Application {
     ///
     /// let mut app = Application::new("0:12345");
     /// ```
-    pub fn new(addr: &'static str) -> Self {
-        env_logger::init();
+    pub fn new(addr: &str) -> Self {
+        let _ = env_logger::try_init();
         let router = Router::default();
         let middlewares = Vec::new();
         let default_num_threads = NonZeroUsize::new(8).unwrap();
         let num_threads = available_parallelism().unwrap_or(default_num_threads).get();
         Self {
-            addr,
+            addr: addr.to_string(),
             num_threads,
+            config: Config {
+                keep_alive: Duration::from_secs(5),
+                limits: Limits::default(),
+                timeouts: Timeouts::default(),
+            },
             router,
+            urls: Arc::default(),
             middlewares,
+            compiled: OnceCell::new(),
+            hooks: Arc::default(),
+            shutdown: ShutdownHandle::default(),
+            shutdown_timeout: Duration::from_secs(30),
+            shutdown_hooks: Vec::new(),
+            queue_capacity: 1024,
+            retry_after: None,
         }
     }
 
@@ -54,6 +160,166 @@ impl Application {
         self
     }
 
+    /// Set how long an idle keep-alive connection is kept open waiting for the next request,
+    /// defaults to 5 seconds. A zero duration disables keep-alive.
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").keep_alive(Duration::from_secs(30));
+    /// ```
+    pub fn keep_alive(mut self, timeout: Duration) -> Self {
+        self.config.keep_alive = timeout;
+        self
+    }
+
+    /// Set how long a client has to send the request line and headers, defaults to 10 seconds.
+    /// Slower clients get a `408 Request Timeout`, a zero duration disables the timeout.
+    ///
+    /// It starts when the connection is accepted or, on a keep-alive connection, when the next
+    /// request starts to arrive.
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").header_timeout(Duration::from_secs(5));
+    /// ```
+    pub fn header_timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeouts.header = timeout;
+        self
+    }
+
+    /// Set how long a client has to send the request body once the headers are received,
+    /// defaults to 30 seconds. Slower clients get a `408 Request Timeout`, a zero duration
+    /// disables the timeout.
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").body_timeout(Duration::from_secs(60));
+    /// ```
+    pub fn body_timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeouts.body = timeout;
+        self
+    }
+
+    /// Set how long writing to a client that doesn't read the response may block before the
+    /// connection is dropped, defaults to 30 seconds. A zero duration disables the timeout.
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").write_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeouts.write = timeout;
+        self
+    }
+
+    /// Set the maximum length of a request URI, longer ones are answered with
+    /// `414 URI Too Long`, defaults to 8 KiB
+    /// # Examples
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").max_uri_length(2048);
+    /// ```
+    pub fn max_uri_length(mut self, n: usize) -> Self {
+        self.config.limits.max_uri_length = n;
+        self
+    }
+
+    /// Set the maximum total size and count of request headers, larger ones are answered with
+    /// `431 Request Header Fields Too Large`, defaults to 16 KiB and 100 headers
+    /// # Examples
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").max_headers(8 * 1024, 50);
+    /// ```
+    pub fn max_headers(mut self, size: usize, count: usize) -> Self {
+        self.config.limits.max_header_size = size;
+        self.config.limits.max_headers = count;
+        self
+    }
+
+    /// Set the maximum size of a request body, larger ones are answered with
+    /// `413 Payload Too Large`, defaults to 10 MiB
+    /// # Examples
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").max_body_size(1024 * 1024);
+    /// ```
+    pub fn max_body_size(mut self, n: usize) -> Self {
+        self.config.limits.max_body_size = n;
+        self
+    }
+
+    /// Set how long a shutdown waits for the requests in flight before `run` returns, defaults
+    /// to 30 seconds
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").shutdown_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set how many accepted connections can wait for a free worker, defaults to 1024
+    ///
+    /// Once the queue is full the application stops accepting connections until a worker is
+    /// free, unless [`reject_when_busy`](Self::reject_when_busy) is set. With a zero capacity
+    /// connections are only handed over to idle workers.
+    /// # Examples
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").queue_capacity(128);
+    /// ```
+    pub fn queue_capacity(mut self, n: usize) -> Self {
+        self.queue_capacity = n;
+        self
+    }
+
+    /// Answer the connections accepted while the queue is full with a
+    /// `503 Service Unavailable` asking to retry after `retry_after`, instead of waiting for a
+    /// free worker
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080")
+    ///     .queue_capacity(128)
+    ///     .reject_when_busy(Duration::from_secs(1));
+    /// ```
+    pub fn reject_when_busy(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Set the size above which files uploaded in a `multipart/form-data` body are spooled to
+    /// temporary files instead of being kept in memory, defaults to 1 MiB
+    /// # Examples
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080").upload_memory_limit(64 * 1024);
+    /// ```
+    pub fn upload_memory_limit(mut self, n: usize) -> Self {
+        self.config.limits.upload_memory_limit = n;
+        self
+    }
+
     /// Add a middleware into an `Application`
     /// # Example
     /// ```
@@ -66,9 +332,23 @@ impl Application {
     where
         M: Fn(DynHandler) -> DynHandler + Send + Sync + 'static,
     {
+        self.compiled.take();
         self.middlewares.push(Arc::new(middleware));
     }
 
+    /// Answer the CORS preflight requests and add the `Access-Control-*` headers to the
+    /// responses, around the hooks and middlewares, a later call replaces the configuration
+    /// # Example
+    /// ```
+    /// use haro::{Application, middleware};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.cors(middleware::cors().allow_origin("https://app.example.com"));
+    /// ```
+    pub fn cors(&mut self, cors: Cors) {
+        Arc::make_mut(&mut self.hooks).cors = Some(cors);
+    }
+
     /// Add a route using a function or closure
     /// # Example
     /// ```
@@ -82,11 +362,91 @@ impl Application {
     ///     Response::str("Hello Haro")
     /// }
     /// ```
-    pub fn route<F>(&mut self, pattern: &'static str, f: F)
+    pub fn route<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(&[], pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route only answering the given methods, other methods get a
+    /// `405 Method Not Allowed`. `HEAD` is answered by the `GET` handler and `OPTIONS`
+    /// lists the allowed methods unless they have their own handlers.
+    /// # Example
+    /// ```
+    /// use http::Method;
+    /// use haro::{Application, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.route_methods("/users", &[Method::GET, Method::POST], |_| Response::str("users"));
+    /// ```
+    pub fn route_methods<F, R>(&mut self, pattern: &str, methods: &[Method], f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(methods, pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route answering `GET` (and `HEAD`) requests
+    /// # Example
+    /// ```
+    /// use haro::{Application, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.get("/users", |_| Response::str("list users"));
+    /// app.post("/users", |_| Response::str("create user"));
+    /// ```
+    pub fn get<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(&[Method::GET], pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route answering `POST` requests
+    pub fn post<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(&[Method::POST], pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route answering `PUT` requests
+    pub fn put<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(&[Method::PUT], pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route answering `PATCH` requests
+    pub fn patch<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().add(&[Method::PATCH], pattern, f);
+        self.named(pattern)
+    }
+
+    /// Add a route answering `DELETE` requests
+    pub fn delete<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
     {
-        self.router.add(pattern, f);
+        self.router_mut().add(&[Method::DELETE], pattern, f);
+        self.named(pattern)
     }
 
     /// Add a route using a trait type
@@ -107,11 +467,244 @@ impl Application {
     ///         Response::str(format!("hello {}", self.name))
     ///     }
     /// }
-    pub fn route_handler<H>(&mut self, pattern: &'static str, h: H)
+    pub fn route_handler<H>(&mut self, pattern: &str, h: H) -> Route<'_>
     where
         H: Handler + Send + Sync + 'static,
     {
-        self.router.add_handler(pattern, h);
+        self.router_mut().add_handler(&[], pattern, h);
+        self.named(pattern)
+    }
+
+    /// Answer the requests matching no route, instead of the default `404 Not Found`
+    ///
+    /// The handler sets the status of its response, so it can also serve a page for any path.
+    /// # Example
+    /// ```
+    /// use haro::{Application, Response};
+    /// use http::StatusCode;
+    /// use serde_json::json;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.not_found(|req| {
+    ///     let body = json!({"error": "not found", "path": req.path()});
+    ///     Response::json(body).with_status(StatusCode::NOT_FOUND)
+    /// });
+    /// ```
+    pub fn not_found<F, R>(&mut self, f: F)
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router_mut().fallback("", handler(f));
+    }
+
+    /// Build the response sent when a handler or middleware panicked, instead of the default
+    /// `500 Internal Server Error`. The handler gets the panic message, which is already logged
+    /// with the method and path of the request.
+    /// # Example
+    /// ```
+    /// use haro::{Application, Error};
+    /// use http::StatusCode;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.panic_handler(|_| Error::html(StatusCode::INTERNAL_SERVER_ERROR, "<h1>Oops</h1>"));
+    /// ```
+    pub fn panic_handler<F, R>(&mut self, f: F)
+    where
+        F: Fn(&str) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Arc::make_mut(&mut self.hooks).panic =
+            Arc::new(move |message: &str| f(message).into_response());
+    }
+
+    /// Add a hook run before the middlewares and the handler of every request, a hook returning
+    /// a `Response` answers the request without running the next hooks and the handler
+    /// # Example
+    /// ```
+    /// use haro::{Application, Response};
+    /// use http::StatusCode;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.before_request(|req| match req.headers().contains_key("authorization") {
+    ///     true => None,
+    ///     false => Some(Response::str("login first").with_status(StatusCode::UNAUTHORIZED)),
+    /// });
+    /// ```
+    pub fn before_request<F>(&mut self, f: F)
+    where
+        F: Fn(&mut Request) -> Option<Response> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks).before.push(Arc::new(f));
+    }
+
+    /// Add a hook run on the response of every request after the middlewares, the handler and
+    /// the error handlers, hooks run in the order they were added. The answers to panics and
+    /// malformed requests go through the hooks too, and a panicking hook is answered like a
+    /// panicking handler.
+    /// # Example
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.after_request(|res| res.header("x-frame-options", "DENY"));
+    /// ```
+    pub fn after_request<F>(&mut self, f: F)
+    where
+        F: Fn(Response) -> Response + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks).after.push(Arc::new(f));
+    }
+
+    /// Add a hook run once a request is answered, even when a handler or a hook panicked. The
+    /// hook gets the panic message if any.
+    /// # Example
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.teardown_request(|panic| {
+    ///     if let Some(message) = panic {
+    ///         eprintln!("request failed: {message}");
+    ///     }
+    /// });
+    /// ```
+    pub fn teardown_request<F>(&mut self, f: F)
+    where
+        F: Fn(Option<&str>) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks).teardown.push(Arc::new(f));
+    }
+
+    /// Rewrite the responses with the given status, whether they come from a handler, a
+    /// middleware, a hook, the router or the [`panic_handler`](Self::panic_handler)
+    ///
+    /// The handler gets the original response and its response is sent as it is, so it sets
+    /// the status again. Registering another handler for the same status replaces it.
+    /// # Example
+    /// ```
+    /// use haro::{Application, Error};
+    /// use http::StatusCode;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.error_handler(StatusCode::NOT_FOUND, |_| {
+    ///     Error::html(StatusCode::NOT_FOUND, "<h1>Nothing here</h1>")
+    /// });
+    /// ```
+    pub fn error_handler<F, R>(&mut self, status: StatusCode, f: F)
+    where
+        F: Fn(Response) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        let hooks = Arc::make_mut(&mut self.hooks);
+        hooks.errors.retain(|(s, _)| *s != status);
+        hooks
+            .errors
+            .push((status, Arc::new(move |res| f(res).into_response())));
+    }
+
+    /// Handle to shut down the `Application` while it runs, see [`ShutdownHandle`]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Add a hook run once the `Application` is shut down and the requests in flight are
+    /// finished, hooks run in the order they were added
+    /// # Example
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.on_shutdown(|| println!("bye"));
+    /// ```
+    pub fn on_shutdown<F>(&mut self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(f));
+    }
+
+    /// Share `value` with every handler and middleware, which get it with
+    /// [`Request::state`]. A value of the same type registered before is replaced.
+    /// # Example
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use haro::{Application, Request, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.state(AtomicUsize::new(0));
+    /// app.route("/", |req: Request| {
+    ///     let hits = req.state::<AtomicUsize>().unwrap();
+    ///     Response::str(format!("{} hits", hits.fetch_add(1, Ordering::Relaxed) + 1))
+    /// });
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.router_mut().state).insert(value);
+    }
+
+    /// Mount the routes of a [`Blueprint`] into an `Application`
+    /// # Example
+    /// ```
+    /// use haro::{Application, Blueprint, Response};
+    ///
+    /// let mut api = Blueprint::new("/api/v1");
+    /// api.get("/users", |_| Response::str("list users"));
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.mount(api);
+    /// ```
+    pub fn mount(&mut self, blueprint: Blueprint) {
+        let (routes, names, fallbacks) = blueprint.into_routes();
+        for (methods, pattern, handler) in routes {
+            self.router_mut().insert(&methods, &pattern, handler);
+        }
+        for (prefix, handler) in fallbacks {
+            self.router_mut().fallback(&prefix, handler);
+        }
+        let urls = Arc::make_mut(&mut self.urls);
+        for (name, pattern) in names {
+            urls.insert(&name, &pattern);
+        }
+    }
+
+    /// Build the URL of a named route, percent-encoding the path parameters and the query
+    /// # Example
+    /// ```
+    /// use haro::{Application, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.get("/users/<name>", |_| Response::str("user")).name("user");
+    ///
+    /// let url = app.url_for("user", &[("name", "Haro Ray")], &[]).unwrap();
+    /// assert_eq!("/users/Haro%20Ray", url);
+    /// assert!(app.url_for("user", &[], &[]).is_err());
+    /// ```
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, UrlError> {
+        self.urls.url_for(name, params, query)
+    }
+
+    fn router_mut(&mut self) -> &mut Router {
+        self.compiled.take();
+        &mut self.router
+    }
+
+    /// Router whose handlers are wrapped with the middlewares once, instead of on every request
+    fn compiled(&self) -> Arc<Router> {
+        let router = self.compiled.get_or_init(|| {
+            let mut router = self.router.clone();
+            router.wrap(&self.middlewares);
+            Arc::new(router)
+        });
+        router.clone()
+    }
+
+    fn named(&mut self, pattern: &str) -> Route<'_> {
+        Route::new(Arc::make_mut(&mut self.urls), pattern)
     }
 
     /// Send a request to an `Application`, usually used in test
@@ -138,18 +731,24 @@ impl Application {
         body: &[u8],
     ) -> Response {
         let mut req = Request::new(method, uri, headers, body);
-        let (params, mut handler) = self.router.dispatch(req.path());
-        req.params = params;
+        let head = req.method() == Method::HEAD;
+        let router = self.compiled();
+        let handler = router.dispatch(&mut req);
+        req.urls = self.urls.clone();
+        req.state = router.state.clone();
 
-        // TODO: how much benefits to move applying middlewares at begging to avoid do it every time in a new request.
-        // apply middleware in reverse order
-        for middleware in self.middlewares.iter().rev() {
-            handler = middleware(handler);
+        let (res, _) = call(&handler, req, &self.hooks);
+        if head {
+            res.without_body()
+        } else {
+            res
         }
-        handler(req)
     }
 
     /// Run the application, start listening on the specify address and start a worker pool to handle requests
+    ///
+    /// It runs until a `SIGTERM`, a `SIGINT` or a [`ShutdownHandle`] shuts it down, then it waits
+    /// for the requests in flight up to the shutdown timeout, runs the shutdown hooks and returns.
     /// # Examples
     /// ```no_run
     /// use std::collections::HashMap;
@@ -163,37 +762,260 @@ impl Application {
     /// app.run()
     /// ```
     pub fn run(&self) {
-        info!("Started web server on addr {}", self.addr);
+        let listener = self.listen(&self.addr);
+        #[cfg(unix)]
+        let signals = crate::shutdown::watch_signals(self.shutdown.clone());
+        self.serve(listener);
+        #[cfg(unix)]
+        if let Some(signals) = signals {
+            signals.close();
+        }
+    }
+
+    /// Run the application in a background thread listening on a free port of `127.0.0.1`,
+    /// usually used in test to send requests over TCP
+    ///
+    /// The configured address is ignored and signals are left alone, the application shuts down
+    /// when the returned [`ServerHandle`] is dropped.
+    /// # Examples
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use std::net::TcpStream;
+    /// use haro::{Application, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.route("/", |_| Response::str("test"));
+    /// let server = app.spawn();
+    ///
+    /// let mut stream = TcpStream::connect(server.addr()).unwrap();
+    /// stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    /// let mut res = String::new();
+    /// stream.read_to_string(&mut res).unwrap();
+    /// assert!(res.ends_with("\r\n\r\ntest"));
+    /// ```
+    pub fn spawn(self) -> ServerHandle {
+        let listener = self.listen("127.0.0.1:0");
+        let addr = listener.local_addr().unwrap();
+        let shutdown = self.shutdown.clone();
+        let thread = thread::spawn(move || self.serve(listener));
+        ServerHandle::new(addr, shutdown, thread)
+    }
+
+    /// Bind `addr` and record it so a shutdown can wake up the accept loop
+    fn listen(&self, addr: &str) -> TcpListener {
+        let listener = TcpListener::bind(addr).unwrap();
+        if let Ok(addr) = listener.local_addr() {
+            self.shutdown.bind(addr);
+        }
+        listener
+    }
+
+    /// Accept connections from `listener` until the application is shut down
+    fn serve(&self, listener: TcpListener) {
+        let addr = listener
+            .local_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| self.addr.clone());
+        info!("Started web server on addr {addr}");
         debug!("routes: \n {:}", self.router);
-        let pool = ThreadPool::new(self.num_threads);
+        let router = self.compiled();
+        let mut pool = ThreadPool::new(self.num_threads, self.queue_capacity);
 
-        let listener = TcpListener::bind(self.addr).unwrap();
-        for stream in listener.incoming() {
-            let stream = stream.unwrap();
-            // TODO: anyway to avoid clone?
-            let router = self.router.clone();
-            let middlewares = self.middlewares.clone();
-            pool.execute(|| {
-                handle_connection(router, middlewares, stream);
-            });
+        // a shutdown requested before the address was recorded can't wake up the accept loop
+        if !self.shutdown.is_shutdown() {
+            for stream in listener.incoming() {
+                if self.shutdown.is_shutdown() {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("failed to accept connection: {e}");
+                        continue;
+                    }
+                };
+                let router = router.clone();
+                let urls = self.urls.clone();
+                let hooks = self.hooks.clone();
+                let shutdown = self.shutdown.clone();
+                let config = self.config;
+                let job = move |stream| {
+                    handle_connection(router, urls, hooks, shutdown, config, stream);
+                };
+                match self.retry_after {
+                    Some(retry_after) => {
+                        if let Err(stream) = pool.try_execute(stream, job) {
+                            reject(stream, retry_after);
+                        }
+                    }
+                    None => pool.execute(move || job(stream)),
+                }
+            }
         }
+
+        drop(listener);
+        info!("Shutting down, waiting for requests in flight");
+        if !pool.shutdown(self.shutdown_timeout) {
+            warn!("requests still running after {:?}", self.shutdown_timeout);
+        }
+        for hook in &self.shutdown_hooks {
+            hook();
+        }
+        info!("Stopped web server on addr {addr}");
     }
 }
 
-fn handle_connection(router: Router, middlewares: Vec<Middleware>, stream: TcpStream) {
-    let mut conn = Conn::from(stream);
-    let mut req = Request::from(&mut conn);
-    let (params, mut handler) = router.dispatch(req.path());
-    req.params = params;
+fn handle_connection(
+    router: Arc<Router>,
+    urls: Arc<Urls>,
+    hooks: Arc<Hooks>,
+    shutdown: ShutdownHandle,
+    config: Config,
+    stream: TcpStream,
+) {
+    let mut conn = match Conn::from(stream, config.timeouts) {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("failed to set up connection: {e}");
+            return;
+        }
+    };
+    loop {
+        let mut req = match Request::from(&mut conn, &config.limits) {
+            Ok(req) => req,
+            Err(ParseError::ConnectionClosed | ParseError::Io(_)) => break,
+            Err(e) => {
+                warn!("failed to parse request: {e}");
+                let res = Response::new(e.status(), e.to_string().as_bytes(), HashMap::new());
+                let (res, _) = hooks.finish(res, "a malformed request");
+                let res = res.header(CONNECTION, "close");
+                let _ = write_response(&mut conn, res, true);
+                break;
+            }
+        };
+        let version = req.version();
+        let head = req.method() == Method::HEAD;
+        let mut persistent =
+            req.keep_alive() && !config.keep_alive.is_zero() && !shutdown.is_shutdown();
+        let handler = router.dispatch(&mut req);
+        req.urls = urls.clone();
+        req.state = router.state.clone();
+        let (mut res, panicked) = call(&handler, req, &hooks);
+        // the state of the connection is unknown after a panic
+        if panicked {
+            res = res.header(CONNECTION, "close");
+        }
+
+        if res.is_chunked() && version < Version::HTTP_11 {
+            res = res.unchunked();
+        }
+        if wants_close(&res) {
+            persistent = false;
+        } else if !persistent {
+            res = res.header(CONNECTION, "close");
+        } else if version == Version::HTTP_10 {
+            res = res.header(CONNECTION, "keep-alive");
+        }
+
+        if let Err(e) = write_response(&mut conn, res, !head) {
+            debug!("failed to write response: {e}");
+            break;
+        }
 
-    // apply middleware in reverse order
-    for middleware in middlewares.iter().rev() {
-        handler = middleware(handler);
+        let stop = || shutdown.is_shutdown();
+        if !persistent || !conn.wait_request(config.keep_alive, &stop) {
+            debug!("closing connection");
+            break;
+        }
+    }
+}
+
+/// Call `handler` with the request hooks around it, a panic in a hook, a middleware or the
+/// handler is logged and answered by the panic handler, returns the response and whether a
+/// panic happened
+fn call(handler: &DynHandler, mut req: Request, hooks: &Hooks) -> (Response, bool) {
+    let context = format!("{} {}", req.method(), req.path());
+    #[cfg(feature = "template")]
+    let _urls = crate::template::scope_urls(req.urls.clone());
+    // preflights are answered before the hooks, which may ask for credentials they don't carry
+    let (origin, preflight) = match &hooks.cors {
+        Some(cors) => cors.start(&req),
+        None => (None, None),
+    };
+    let is_preflight = preflight.is_some();
+    let result = catch(|| {
+        if let Some(res) = preflight {
+            return res;
+        }
+        match hooks.before.iter().find_map(|hook| hook(&mut req)) {
+            Some(res) => res,
+            None => handler(req),
+        }
+    });
+    let (res, panic) = match result {
+        Ok(res) => (res, None),
+        Err(message) => {
+            error!("panic while handling {context}: {message}");
+            ((hooks.panic)(&message), Some(message))
+        }
+    };
+    // the answers to panics go through the error handlers and after hooks too
+    let (mut res, hook_panic) = hooks.finish(res, &context);
+    let panic = panic.or(hook_panic);
+    if let Some(cors) = &hooks.cors {
+        res = cors.decorate(res, origin.as_ref(), is_preflight);
+    }
+    for hook in &hooks.teardown {
+        hook(panic.as_deref());
+    }
+    (res, panic.is_some())
+}
+
+/// Run `f`, returns the message of its panic if it panics
+fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic")
+            .to_string()
+    })
+}
+
+/// Answer a connection with a `503 Service Unavailable` without reading its request
+fn reject(stream: TcpStream, retry_after: Duration) {
+    warn!("worker queue is full, rejecting connection");
+    // don't let a slow client hold up the accept loop
+    let _ = stream.set_write_timeout(Some(REJECT_WRITE_TIMEOUT));
+    // round up so clients don't retry right away
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let res = Response::str("503 Service Unavailable")
+        .with_status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(RETRY_AFTER, secs.to_string())
+        .header(CONNECTION, "close");
+    let mut w = io::BufWriter::new(&stream);
+    if res.write_to(&mut w, true).and_then(|_| w.flush()).is_ok() {
+        let _ = stream.shutdown(Shutdown::Write);
     }
-    let res = handler(req);
+}
+
+fn internal_server_error(_message: &str) -> Response {
+    Response::str("500 Internal Server Error").with_status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn write_response(conn: &mut Conn, res: Response, with_body: bool) -> io::Result<()> {
+    res.write_to(conn, with_body)?;
+    conn.flush()
+}
 
-    conn.write_all(res.to_string().as_bytes());
-    conn.flush();
+/// Whether a handler or middleware asked to close the connection after the `Response`
+fn wants_close(res: &Response) -> bool {
+    res.headers()
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case("close")))
 }
 
 #[cfg(test)]
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use http::Method;
+
+use crate::middleware::Middleware;
+use crate::router::{handler, Route, Urls};
+use crate::{DynHandler, Handler, IntoResponse, Request};
+
+/// Methods, pattern and handler of the routes in a [`Blueprint`]
+pub(crate) type Routes = Vec<(Vec<Method>, String, DynHandler)>;
+/// Names and patterns of the named routes
+pub(crate) type Names = Vec<(String, String)>;
+/// Prefix and handler of the fallbacks for paths matching no route
+pub(crate) type Fallbacks = Vec<(String, DynHandler)>;
+
+/// A group of routes sharing a URL prefix and a middleware stack
+///
+/// Middlewares of a `Blueprint` are only applied to its own routes, inside the middlewares of
+/// the `Application` and of the blueprints it is nested in.
+/// # Example
+/// ```
+/// use haro::{Application, Blueprint, Response, middleware};
+///
+/// let mut users = Blueprint::new("/users");
+/// users.get("/", |_| Response::str("list users"));
+/// users.get("/<id:int>", |req| Response::str(&req.params["id"]));
+///
+/// let mut api = Blueprint::new("/api/v1");
+/// api.middleware(middleware::logging);
+/// api.mount(users);
+///
+/// let mut app = Application::new("0:8080");
+/// app.mount(api);
+/// ```
+pub struct Blueprint {
+    prefix: String,
+    routes: Routes,
+    urls: Urls,
+    not_found: Option<DynHandler>,
+    middlewares: Vec<Middleware>,
+    blueprints: Vec<Blueprint>,
+}
+
+impl Blueprint {
+    /// Create a new `Blueprint` whose routes are all under `prefix`
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            routes: Vec::new(),
+            urls: Urls::default(),
+            not_found: None,
+            middlewares: Vec::new(),
+            blueprints: Vec::new(),
+        }
+    }
+
+    /// Add a middleware applied to the routes of this `Blueprint` and its nested blueprints
+    pub fn middleware<M>(&mut self, middleware: M)
+    where
+        M: Fn(DynHandler) -> DynHandler + Send + Sync + 'static,
+    {
+        self.middlewares.push(Arc::new(middleware));
+    }
+
+    /// Answer the requests under the prefix of this `Blueprint` matching no route, the
+    /// fallback of the innermost blueprint wins
+    pub fn not_found<F, R>(&mut self, f: F)
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.not_found = Some(handler(f));
+    }
+
+    /// Nest a `Blueprint` under the prefix of this one
+    pub fn mount(&mut self, blueprint: Blueprint) {
+        self.blueprints.push(blueprint);
+    }
+
+    /// Add a route using a function or closure
+    pub fn route<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[], f)
+    }
+
+    /// Add a route only answering the given methods
+    pub fn route_methods<F, R>(&mut self, pattern: &str, methods: &[Method], f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.routes
+            .push((methods.to_vec(), pattern.to_string(), handler(f)));
+        Route::new(&mut self.urls, pattern)
+    }
+
+    /// Add a route using a trait type
+    pub fn route_handler<H>(&mut self, pattern: &str, h: H) -> Route<'_>
+    where
+        H: Handler + Send + Sync + 'static,
+    {
+        self.routes
+            .push((Vec::new(), pattern.to_string(), h.handler()));
+        Route::new(&mut self.urls, pattern)
+    }
+
+    /// Add a route answering `GET` (and `HEAD`) requests
+    pub fn get<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[Method::GET], f)
+    }
+
+    /// Add a route answering `POST` requests
+    pub fn post<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[Method::POST], f)
+    }
+
+    /// Add a route answering `PUT` requests
+    pub fn put<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[Method::PUT], f)
+    }
+
+    /// Add a route answering `PATCH` requests
+    pub fn patch<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[Method::PATCH], f)
+    }
+
+    /// Add a route answering `DELETE` requests
+    pub fn delete<F, R>(&mut self, pattern: &str, f: F) -> Route<'_>
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.route_methods(pattern, &[Method::DELETE], f)
+    }
+
+    /// Flatten the routes of this `Blueprint` and its nested blueprints, with full patterns and
+    /// handlers wrapped by the middlewares, along with the names and full patterns of the named
+    /// routes and the fallbacks
+    pub(crate) fn into_routes(self) -> (Routes, Names, Fallbacks) {
+        let mut routes = self.routes;
+        let mut fallbacks: Fallbacks = self
+            .not_found
+            .into_iter()
+            .map(|h| (String::new(), h))
+            .collect();
+        let mut names: Names = self
+            .urls
+            .iter()
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+            .collect();
+        for blueprint in self.blueprints {
+            let (nested_routes, nested_names, nested_fallbacks) = blueprint.into_routes();
+            routes.extend(nested_routes);
+            names.extend(nested_names);
+            fallbacks.extend(nested_fallbacks);
+        }
+        let names = names
+            .into_iter()
+            .map(|(name, pattern)| (name, format!("{}{pattern}", self.prefix)))
+            .collect();
+        let wrap = |mut handler: DynHandler| {
+            // apply middleware in reverse order
+            for middleware in self.middlewares.iter().rev() {
+                handler = middleware(handler);
+            }
+            handler
+        };
+        let routes = routes
+            .into_iter()
+            .map(|(methods, pattern, handler)| {
+                (methods, format!("{}{pattern}", self.prefix), wrap(handler))
+            })
+            .collect();
+        let fallbacks = fallbacks
+            .into_iter()
+            .map(|(prefix, handler)| (format!("{}{prefix}", self.prefix), wrap(handler)))
+            .collect();
+        (routes, names, fallbacks)
+    }
+}
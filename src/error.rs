@@ -0,0 +1,138 @@
+//! Error type for fallible handlers
+use std::{collections::HashMap, fmt::Display};
+
+use http::{header::CONTENT_TYPE, StatusCode};
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::http::request::{ExtractError, ParamError};
+use crate::http::response::{IntoResponse, Response};
+
+/// Error returned by a handler, answered with its status code and body
+///
+/// Any [`std::error::Error`] converts into an `Error` with `?`. Errors of the framework keep
+/// their status such as `400 Bad Request` for an [`ExtractError`], other errors become a
+/// `500 Internal Server Error` whose details are logged but not sent to the client.
+/// # Example
+/// ```
+/// use haro::{Application, Error, Request, Response};
+/// use http::StatusCode;
+///
+/// let mut app = Application::new("0:8080");
+/// app.get("/files/<name>", read);
+///
+/// fn read(req: Request) -> Result<Response, Error> {
+///     let name = &req.params["name"];
+///     if name.starts_with('.') {
+///         return Err(Error::text(StatusCode::FORBIDDEN, "hidden file"));
+///     }
+///     let content = std::fs::read_to_string(name)?;
+///     Ok(Response::str(content))
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Error {
+    status: StatusCode,
+    body: ErrorBody,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+#[derive(Debug)]
+enum ErrorBody {
+    /// The status code and its reason as plain text
+    Status,
+    Text(String),
+    Json(Value),
+    Html(String),
+}
+
+impl Error {
+    /// Create an `Error` answered with the status code and its reason, such as
+    /// `404 Not Found`
+    pub fn new(status: StatusCode) -> Self {
+        Self::with_body(status, ErrorBody::Status)
+    }
+
+    /// Create an `Error` with a plain text body
+    pub fn text<T: Into<String>>(status: StatusCode, text: T) -> Self {
+        Self::with_body(status, ErrorBody::Text(text.into()))
+    }
+
+    /// Create an `Error` with a JSON body
+    pub fn json<S: Serialize>(status: StatusCode, s: S) -> Self {
+        let value = serde_json::to_value(s).unwrap_or_default();
+        Self::with_body(status, ErrorBody::Json(value))
+    }
+
+    /// Create an `Error` with an HTML body
+    pub fn html<T: Into<String>>(status: StatusCode, html: T) -> Self {
+        Self::with_body(status, ErrorBody::Html(html.into()))
+    }
+
+    fn with_body(status: StatusCode, body: ErrorBody) -> Self {
+        Self {
+            status,
+            body,
+            source: None,
+        }
+    }
+
+    /// HTTP status code of the response for this error
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Error this one was converted from
+    pub fn source(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        self.source.as_deref()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {source}", self.status),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn from(e: E) -> Self {
+        let source: Box<dyn std::error::Error + Send + Sync> = Box::new(e);
+        let mut error = if let Some(e) = source.downcast_ref::<ParamError>() {
+            Self::text(e.status(), e.to_string())
+        } else if let Some(e) = source.downcast_ref::<ExtractError>() {
+            Self::text(e.status(), e.to_string())
+        } else {
+            Self::new(StatusCode::INTERNAL_SERVER_ERROR)
+        };
+        error.source = Some(source);
+        error
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            error!("handler failed: {}", self);
+        }
+        match self.body {
+            ErrorBody::Status => {
+                let reason = self.status.canonical_reason().unwrap_or_default();
+                let body = format!("{} {reason}", self.status.as_u16());
+                Response::str(body).with_status(self.status)
+            }
+            ErrorBody::Text(text) => Response::str(text).with_status(self.status),
+            ErrorBody::Json(value) => Response::json(value).with_status(self.status),
+            ErrorBody::Html(html) => {
+                let headers = HashMap::from([(CONTENT_TYPE, "text/html; charset=utf-8")]);
+                Response::new(self.status, html.as_bytes(), headers)
+            }
+        }
+    }
+}
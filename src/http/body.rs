@@ -0,0 +1,111 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{self, Read, Write},
+};
+
+/// Size of the buffer used to copy a streamed body to the connection
+const COPY_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Body of a [`Response`](crate::Response)
+///
+/// Only `Bytes` is held in memory, the other variants are written to the connection
+/// incrementally while the response is sent.
+pub enum Body {
+    /// Bytes in memory
+    Bytes(Vec<u8>),
+    /// A reader of unknown length, sent with `Transfer-Encoding: chunked`
+    Reader(Box<dyn Read + Send>),
+    /// An iterator of chunks, sent with `Transfer-Encoding: chunked`
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+    /// A file of known length, sent with `Content-Length`
+    File(File),
+}
+
+impl Body {
+    /// Bytes of the body if it is held in memory
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Read the whole body into memory
+    pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Bytes(bytes) => return Ok(bytes),
+            Self::Reader(mut reader) => {
+                reader.read_to_end(&mut buf)?;
+            }
+            Self::Chunks(chunks) => chunks.for_each(|chunk| buf.extend(chunk)),
+            Self::File(mut file) => {
+                file.read_to_end(&mut buf)?;
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Write the body into `w`, framing it in chunks if `chunked` is set
+    pub(crate) fn write_to<W: Write>(self, w: &mut W, chunked: bool) -> io::Result<()> {
+        match self {
+            Self::Bytes(bytes) => write_part(w, &bytes, chunked)?,
+            Self::Reader(mut reader) => copy(&mut reader, w, chunked)?,
+            Self::Chunks(chunks) => {
+                for chunk in chunks {
+                    write_part(w, &chunk, chunked)?;
+                }
+            }
+            Self::File(mut file) => copy(&mut file, w, chunked)?,
+        }
+        if chunked {
+            w.write_all(b"0\r\n\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Self::Reader(_) => f.write_str("Reader"),
+            Self::Chunks(_) => f.write_str("Chunks"),
+            Self::File(file) => f.debug_tuple("File").field(file).finish(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+/// Write a piece of the body, empty pieces are skipped as an empty chunk ends the body
+fn write_part<W: Write>(w: &mut W, data: &[u8], chunked: bool) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    if chunked {
+        write!(w, "{:x}\r\n", data.len())?;
+        w.write_all(data)?;
+        w.write_all(b"\r\n")
+    } else {
+        w.write_all(data)
+    }
+}
+
+fn copy<R: Read, W: Write>(reader: &mut R, w: &mut W, chunked: bool) -> io::Result<()> {
+    let mut buf = [0; COPY_BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write_part(w, &buf[..n], chunked)?;
+    }
+}
@@ -1,31 +1,148 @@
 use std::{
-    io::{BufRead, BufReader, BufWriter, Read, Write},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
     net::TcpStream,
+    time::{Duration, Instant},
 };
 
+/// How often an idle connection checks whether it should stop waiting
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time limits applied while reading a request and writing its response, a zero duration
+/// disables the limit
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    /// Time to receive the request line and headers
+    pub header: Duration,
+    /// Time to receive the body once the headers are read
+    pub body: Duration,
+    /// Time a single write to the connection may block
+    pub write: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            header: Duration::from_secs(10),
+            body: Duration::from_secs(30),
+            write: Duration::from_secs(30),
+        }
+    }
+}
+
 pub struct Conn {
     reader: BufReader<TcpStream>,
     writer: BufWriter<TcpStream>,
+    timeouts: Timeouts,
+    /// Reads fail with `TimedOut` once it is passed
+    deadline: Option<Instant>,
 }
 
 impl Conn {
-    pub fn from(stream: TcpStream) -> Self {
-        let stream_clone = stream.try_clone().expect("clone failed...");
+    pub fn from(stream: TcpStream, timeouts: Timeouts) -> io::Result<Self> {
+        stream.set_write_timeout(enabled(timeouts.write))?;
+        let stream_clone = stream.try_clone()?;
         let reader = BufReader::new(stream);
         let writer = BufWriter::new(stream_clone);
-        Conn { reader, writer }
+        Ok(Conn {
+            reader,
+            writer,
+            timeouts,
+            deadline: None,
+        })
+    }
+
+    /// Give the request line and headers of the next request the header timeout to arrive
+    pub fn start_head(&mut self) {
+        self.deadline = enabled(self.timeouts.header).map(|t| Instant::now() + t);
+    }
+
+    /// Give the body of the request the body timeout to arrive
+    pub fn start_body(&mut self) {
+        self.deadline = enabled(self.timeouts.body).map(|t| Instant::now() + t);
+    }
+
+    /// Stop limiting the time reads take
+    pub fn clear_deadline(&mut self) -> io::Result<()> {
+        self.deadline = None;
+        self.reader.get_ref().set_read_timeout(None)
+    }
+
+    /// Make the next read from the socket fail when the deadline is passed
+    fn arm(&mut self) -> io::Result<()> {
+        let Some(deadline) = self.deadline else {
+            return Ok(());
+        };
+        // the buffered bytes are returned without reading from the socket
+        if !self.reader.buffer().is_empty() {
+            return Ok(());
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.reader.get_ref().set_read_timeout(Some(left))
     }
 
-    pub fn read_line(&mut self, buf: &mut String) {
-        self.reader.read_line(buf).unwrap();
+    /// Wait up to `timeout` for the next request on the connection, returns false when the
+    /// peer closed the connection, nothing arrived in time or `stop` returned true.
+    ///
+    /// Bytes of pipelined requests already sitting in the read buffer are returned immediately.
+    pub fn wait_request(&mut self, timeout: Duration, stop: &dyn Fn() -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || stop() {
+                return false;
+            }
+            let poll = left.min(WAIT_POLL_INTERVAL);
+            if self.reader.get_ref().set_read_timeout(Some(poll)).is_err() {
+                return false;
+            }
+            match self.reader.fill_buf() {
+                Ok(buf) => {
+                    let ready = !buf.is_empty();
+                    return ready && self.reader.get_ref().set_read_timeout(None).is_ok();
+                }
+                Err(e) if is_timeout(&e) => continue,
+                Err(_) => return false,
+            }
+        }
     }
-    pub fn read_exact(&mut self, buf: &mut [u8]) {
-        self.reader.read_exact(buf).unwrap();
+}
+
+fn enabled(timeout: Duration) -> Option<Duration> {
+    (!timeout.is_zero()).then_some(timeout)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+impl Read for Conn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.reader.read(buf)
     }
-    pub fn write_all(&mut self, buf: &[u8]) {
-        self.writer.write_all(buf).unwrap();
+}
+
+impl BufRead for Conn {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.arm()?;
+        self.reader.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Write for Conn {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
     }
-    pub fn flush(&mut self) {
-        self.writer.flush().unwrap();
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
     }
 }
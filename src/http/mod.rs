@@ -1,7 +1,10 @@
 //! HTTP utilities
 //!
 //!
+pub mod body;
 pub mod conn;
+pub mod multimap;
+pub mod multipart;
 pub mod request;
 pub mod response;
-mod utils;
+pub(crate) mod utils;
@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, Cursor, Read, Write},
+    path::Path,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::http::{
+    request::{Limits, ParseError},
+    utils::read_headers,
+};
+
+/// A file uploaded in a `multipart/form-data` body
+///
+/// Small files are kept in memory, files larger than the configured size are spooled to a
+/// temporary file removed when the `UploadedFile` is dropped.
+#[derive(Debug)]
+pub struct UploadedFile {
+    filename: String,
+    content_type: Option<String>,
+    size: u64,
+    data: Data,
+}
+
+#[derive(Debug)]
+enum Data {
+    Memory(Vec<u8>),
+    Temp(NamedTempFile),
+}
+
+impl UploadedFile {
+    /// File name sent by the client, it should not be trusted as a path
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Content type sent by the client for this file
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Path of the temporary file, `None` if the file is kept in memory
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            Data::Memory(_) => None,
+            Data::Temp(file) => Some(file.path()),
+        }
+    }
+
+    /// Reader over the content of the file
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(match &self.data {
+            Data::Memory(bytes) => Box::new(Cursor::new(bytes)),
+            Data::Temp(file) => Box::new(file.reopen()?),
+        })
+    }
+
+    /// Read the whole content of the file into memory
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            Data::Memory(bytes) => Ok(bytes.clone()),
+            Data::Temp(file) => fs::read(file.path()),
+        }
+    }
+
+    /// Copy the content of the file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        io::copy(&mut self.reader()?, &mut file)?;
+        Ok(())
+    }
+}
+
+/// Fields and files of a `multipart/form-data` body
+#[derive(Default)]
+pub(crate) struct Multipart {
+    pub fields: HashMap<String, String>,
+    pub files: HashMap<String, Vec<UploadedFile>>,
+}
+
+/// Boundary parameter of a `multipart/form-data` content type
+pub(crate) fn boundary(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        (key.trim().eq_ignore_ascii_case("boundary") && !value.is_empty()).then_some(value)
+    })
+}
+
+/// Parse a `multipart/form-data` body while it is read from `r`, the whole body is consumed
+pub(crate) fn parse<R: BufRead>(
+    r: R,
+    boundary: &str,
+    limits: &Limits,
+) -> Result<Multipart, ParseError> {
+    // the first boundary may not be preceded by a line break
+    let mut r = Cursor::new(b"\r\n").chain(r);
+    let delimiter = format!("\r\n--{boundary}").into_bytes();
+    let mut multipart = Multipart::default();
+
+    // skip the preamble
+    read_until(&mut r, &delimiter, &mut |_| Ok(()))?;
+    loop {
+        let mut end = [0; 2];
+        r.read_exact(&mut end)?;
+        match &end {
+            b"--" => break,
+            b"\r\n" => {}
+            _ => return Err(ParseError::InvalidMultipart),
+        }
+
+        let headers = read_headers(&mut r, limits)?;
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        let disposition = header("content-disposition").ok_or(ParseError::InvalidMultipart)?;
+        let (name, filename) = parse_disposition(disposition)?;
+        match filename {
+            Some(filename) => {
+                let content_type = header("content-type").map(str::to_string);
+                let mut spool = Spool::new(limits.upload_memory_limit);
+                read_until(&mut r, &delimiter, &mut |data| spool.write_all(data))?;
+                // no file was selected in the form
+                if filename.is_empty() && spool.size == 0 {
+                    continue;
+                }
+                let file = UploadedFile {
+                    filename,
+                    content_type,
+                    size: spool.size,
+                    data: spool.data,
+                };
+                multipart.files.entry(name).or_default().push(file);
+            }
+            None => {
+                let mut value = Vec::new();
+                read_until(&mut r, &delimiter, &mut |data| {
+                    value.extend_from_slice(data);
+                    Ok(())
+                })?;
+                let value = String::from_utf8_lossy(&value).into_owned();
+                multipart.fields.insert(name, value);
+            }
+        }
+    }
+
+    // skip the epilogue
+    io::copy(&mut r, &mut io::sink())?;
+    Ok(multipart)
+}
+
+/// Name and file name of a `Content-Disposition: form-data` header
+fn parse_disposition(value: &str) -> Result<(String, Option<String>), ParseError> {
+    let mut params = value.split(';');
+    let kind = params.next().unwrap_or_default().trim();
+    if !kind.eq_ignore_ascii_case("form-data") {
+        return Err(ParseError::InvalidMultipart);
+    }
+    let mut name = None;
+    let mut filename = None;
+    for param in params {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .replace("\\\"", "\"");
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            _ => {}
+        }
+    }
+    let name = name.ok_or(ParseError::InvalidMultipart)?;
+    Ok((name, filename))
+}
+
+/// Pass the bytes of `r` to `sink` until `delimiter`, which is consumed but not passed
+fn read_until<R: BufRead>(
+    r: &mut R,
+    delimiter: &[u8],
+    sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> Result<(), ParseError> {
+    // bytes already consumed that may be the start of the delimiter
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            return Err(ParseError::InvalidMultipart);
+        }
+        let mut data = std::mem::take(&mut pending);
+        let carried = data.len();
+        data.extend_from_slice(buf);
+        if let Some(i) = find(&data, delimiter) {
+            sink(&data[..i])?;
+            r.consume(i + delimiter.len() - carried);
+            return Ok(());
+        }
+        let keep = (delimiter.len() - 1).min(data.len());
+        let n = buf.len();
+        sink(&data[..data.len() - keep])?;
+        pending = data[data.len() - keep..].to_vec();
+        r.consume(n);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Destination of an uploaded file, moved to a temporary file once larger than `limit`
+struct Spool {
+    limit: usize,
+    size: u64,
+    data: Data,
+}
+
+impl Spool {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            size: 0,
+            data: Data::Memory(Vec::new()),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Data::Memory(bytes) = &self.data {
+            if bytes.len() + buf.len() > self.limit {
+                let mut file = NamedTempFile::new()?;
+                file.write_all(bytes)?;
+                self.data = Data::Temp(file);
+            }
+        }
+        match &mut self.data {
+            Data::Memory(bytes) => bytes.extend_from_slice(buf),
+            Data::Temp(file) => file.write_all(buf)?,
+        }
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.data {
+            Data::Memory(_) => Ok(()),
+            Data::Temp(file) => file.flush(),
+        }
+    }
+}
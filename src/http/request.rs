@@ -1,24 +1,253 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Read},
+    str::FromStr,
+    sync::Arc,
+};
 
 use cookie::Cookie;
 use http::{
-    header::{CONTENT_LENGTH, CONTENT_TYPE, COOKIE},
-    HeaderMap, HeaderValue, Request as HttpRequest, Version,
+    header::{HeaderName, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, TRANSFER_ENCODING},
+    Extensions, HeaderMap, HeaderValue, Method, Request as HttpRequest, StatusCode, Uri, Version,
 };
 use log::warn;
+use serde::de::DeserializeOwned;
 
 use crate::http::{
     conn::Conn,
-    utils::{parse_json_body, parse_query, read_headers},
+    multimap::MultiMap,
+    multipart::{self, Multipart, UploadedFile},
+    response::{IntoResponse, Response},
+    utils::{
+        parse_form_body, parse_json_body, parse_query, parse_urlencoded, read_chunked_body,
+        read_headers, read_line,
+    },
 };
+use crate::router::{UrlError, Urls};
+use crate::state::State;
+
+/// Room left on the request line for the method, version and separators besides the URI
+const REQUEST_LINE_OVERHEAD: usize = 32;
+
+/// Size limits applied while reading a `Request` from a connection
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limits {
+    pub max_uri_length: usize,
+    pub max_header_size: usize,
+    pub max_headers: usize,
+    pub max_body_size: usize,
+    pub upload_memory_limit: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_uri_length: 8 * 1024,
+            max_header_size: 16 * 1024,
+            max_headers: 100,
+            max_body_size: 10 * 1024 * 1024,
+            upload_memory_limit: 1024 * 1024,
+        }
+    }
+}
+
+/// Error returned when a `Request` can't be read from a connection
+#[derive(Debug)]
+pub enum ParseError {
+    /// The peer closed the connection before a complete request was received
+    ConnectionClosed,
+    /// Reading from the connection failed
+    Io(io::Error),
+    /// The request line is not `METHOD URI VERSION`
+    InvalidRequestLine,
+    /// A header line is malformed or contains invalid characters
+    InvalidHeader,
+    /// The request line or a header is not valid UTF-8
+    InvalidEncoding,
+    /// The `Content-Length` header is not a valid length
+    InvalidContentLength,
+    /// The `Transfer-Encoding` header is not `chunked`
+    UnsupportedTransferEncoding,
+    /// A chunk of a chunked body is malformed
+    InvalidChunk,
+    /// A `multipart/form-data` body is malformed
+    InvalidMultipart,
+    /// The URI is longer than the configured limit
+    UriTooLong,
+    /// The headers are larger than the configured limit
+    HeadersTooLarge,
+    /// The body is larger than the configured limit
+    PayloadTooLarge,
+    /// The headers or the body were not received within the configured timeout
+    Timeout,
+}
+
+impl ParseError {
+    /// HTTP status code of the response sent back for this error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::UriTooLong => StatusCode::URI_TOO_LONG,
+            Self::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedTransferEncoding => StatusCode::NOT_IMPLEMENTED,
+            Self::Timeout => StatusCode::REQUEST_TIMEOUT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConnectionClosed => write!(f, "connection closed"),
+            Self::Io(e) => write!(f, "io error: {e}"),
+            Self::InvalidRequestLine => write!(f, "invalid request line"),
+            Self::InvalidHeader => write!(f, "invalid header"),
+            Self::InvalidEncoding => write!(f, "invalid encoding"),
+            Self::InvalidContentLength => write!(f, "invalid content length"),
+            Self::UnsupportedTransferEncoding => write!(f, "unsupported transfer encoding"),
+            Self::InvalidChunk => write!(f, "invalid chunk"),
+            Self::InvalidMultipart => write!(f, "invalid multipart body"),
+            Self::UriTooLong => write!(f, "uri too long"),
+            Self::HeadersTooLarge => write!(f, "request header fields too large"),
+            Self::PayloadTooLarge => write!(f, "payload too large"),
+            Self::Timeout => write!(f, "request timeout"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Error returned by [`Request::param`] when a path parameter is missing or can't be converted
+#[derive(Debug)]
+pub enum ParamError {
+    /// The route has no parameter with this name
+    Missing(String),
+    /// The parameter value can't be converted into the requested type
+    Invalid { name: String, value: String },
+}
+
+impl ParamError {
+    /// HTTP status code of the response for this error, `404 Not Found` for a missing
+    /// parameter and `400 Bad Request` for an invalid one
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Missing(_) => StatusCode::NOT_FOUND,
+            Self::Invalid { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "missing path parameter {name}"),
+            Self::Invalid { name, value } => {
+                write!(f, "invalid value {value:?} for path parameter {name}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl From<ParamError> for Response {
+    fn from(e: ParamError) -> Self {
+        Response::new(e.status(), e.to_string().as_bytes(), HashMap::new())
+    }
+}
+
+impl IntoResponse for ParamError {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+/// Error returned when a part of a `Request` can't be deserialized into the requested type
+#[derive(Debug)]
+pub struct ExtractError {
+    source: &'static str,
+    field: Option<String>,
+    message: String,
+}
+
+impl ExtractError {
+    fn new(source: &'static str, field: Option<String>, message: String) -> Self {
+        Self {
+            source,
+            field,
+            message,
+        }
+    }
+
+    fn from_path<E: Display>(source: &'static str, e: serde_path_to_error::Error<E>) -> Self {
+        let field = e.path().to_string();
+        let field = (field != ".").then_some(field);
+        Self::new(source, field, e.inner().to_string())
+    }
+
+    /// Path of the invalid field, such as `user.age` or `tags[1]`
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    /// Reason why the value was rejected
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// HTTP status code of the response for this error, always `400 Bad Request`
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+impl Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "invalid {}: {field}: {}", self.source, self.message),
+            None => write!(f, "invalid {}: {}", self.source, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<ExtractError> for Response {
+    fn from(e: ExtractError) -> Self {
+        Response::new(e.status(), e.to_string().as_bytes(), HashMap::new())
+    }
+}
+
+impl IntoResponse for ExtractError {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Self::ConnectionClosed,
+            io::ErrorKind::InvalidData => Self::InvalidEncoding,
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Self::Timeout,
+            _ => Self::Io(e),
+        }
+    }
+}
 
 /// HTTP Request
 #[derive(Debug)]
 pub struct Request {
     req: HttpRequest<Vec<u8>>,
-    pub args: HashMap<String, String>,
+    trailers: HeaderMap<HeaderValue>,
+    pub args: MultiMap,
     pub data: HashMap<String, String>,
     pub params: HashMap<String, String>,
+    files: HashMap<String, Vec<UploadedFile>>,
+    pub(crate) urls: Arc<Urls>,
+    pub(crate) state: Arc<State>,
 }
 
 impl Request {
@@ -33,7 +262,8 @@ impl Request {
     /// let mut req = Request::new("get", "/", headers, body);
     /// ```
     pub fn new(method: &str, uri: &str, headers: HashMap<String, String>, body: &[u8]) -> Self {
-        let mut builder = HttpRequest::builder().method(method).uri(uri);
+        let method = method.to_uppercase();
+        let mut builder = HttpRequest::builder().method(method.as_str()).uri(uri);
         let content_length = body.len();
         let mut content_type = String::new();
         for (key, value) in headers {
@@ -49,77 +279,140 @@ impl Request {
             .unwrap();
 
         let args = parse_query(req.uri().query());
-        let mut data = HashMap::new();
+        let mut body = Multipart::default();
         if content_length > 0 {
-            data = match content_type.as_str() {
-                "application/json" => parse_json_body(req.body()),
-                _ => {
-                    warn!("unsupported content type {}", content_type);
-                    HashMap::new()
-                }
-            };
+            body = parse_body(&content_type, req.body(), &Limits::default()).unwrap_or_else(|e| {
+                warn!("failed to parse body: {}", e);
+                Multipart::default()
+            });
         }
         Self {
             req,
+            trailers: HeaderMap::new(),
             args,
-            data,
+            data: body.fields,
             params: HashMap::new(),
+            files: body.files,
+            urls: Arc::default(),
+            state: Arc::default(),
         }
     }
-    /// Create a new `Request` from a TCP connectio
-    pub fn from(conn: &mut Conn) -> Self {
+    /// Create a new `Request` from a TCP connection, failing with a [`ParseError`] if the
+    /// request is malformed or exceeds the `limits`
+    pub(crate) fn from(conn: &mut Conn, limits: &Limits) -> Result<Self, ParseError> {
         // parse method, uri and version
-        let mut buf = String::new();
-        conn.read_line(&mut buf);
-        let line: Vec<&str> = buf.trim().split(' ').collect();
-        let (method, uri, version) = (line[0], line[1], line[2]);
+        conn.start_head();
+        let limit = limits.max_uri_length + REQUEST_LINE_OVERHEAD;
+        let buf = read_line(conn, limit, ParseError::UriTooLong)?;
+        let mut line = buf.split_ascii_whitespace();
+        let (Some(method), Some(uri), Some(version), None) =
+            (line.next(), line.next(), line.next(), line.next())
+        else {
+            return Err(ParseError::InvalidRequestLine);
+        };
+        if uri.len() > limits.max_uri_length {
+            return Err(ParseError::UriTooLong);
+        }
         let version = match version {
+            "HTTP/1.0" => Version::HTTP_10,
+            "HTTP/1.1" => Version::HTTP_11,
             "HTTP/2.0" => Version::HTTP_2,
             "HTTP/3.0" => Version::HTTP_3,
-            _ => Version::HTTP_11,
+            _ => return Err(ParseError::InvalidRequestLine),
         };
+        let method =
+            Method::from_bytes(method.as_bytes()).map_err(|_| ParseError::InvalidRequestLine)?;
+        let uri = Uri::try_from(uri).map_err(|_| ParseError::InvalidRequestLine)?;
         let mut builder = HttpRequest::builder()
             .method(method)
             .uri(uri)
             .version(version);
 
         // parse headers
-        let mut content_length = 0;
+        let mut content_length = None;
         let mut content_type = String::new();
-        for (key, value) in read_headers(conn) {
-            if key.to_lowercase() == CONTENT_LENGTH.as_str().to_lowercase() {
-                content_length = value.parse().unwrap();
-            } else if key.to_lowercase() == CONTENT_TYPE.as_str().to_lowercase() {
-                content_type = value.clone();
+        let mut chunked = false;
+        for (key, value) in read_headers(conn, limits)? {
+            let key = HeaderName::try_from(key).map_err(|_| ParseError::InvalidHeader)?;
+            let value = HeaderValue::try_from(value).map_err(|_| ParseError::InvalidHeader)?;
+            if key == CONTENT_LENGTH {
+                let length = value
+                    .to_str()
+                    .ok()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .ok_or(ParseError::InvalidContentLength)?;
+                if content_length.replace(length).is_some_and(|l| l != length) {
+                    return Err(ParseError::InvalidContentLength);
+                }
+            } else if key == CONTENT_TYPE {
+                content_type = value.to_str().unwrap_or_default().to_string();
+            } else if key == TRANSFER_ENCODING {
+                // chunked must be the final encoding, other codings are not supported
+                chunked = match value.to_str().map(|v| v.trim().to_ascii_lowercase()) {
+                    Ok(v) if v == "chunked" => true,
+                    _ => return Err(ParseError::UnsupportedTransferEncoding),
+                };
             }
             builder = builder.header(key, value);
         }
 
         // parse body
-        let mut body = Vec::<u8>::new();
-        body.resize(content_length, 0);
-        conn.read_exact(&mut body);
-        let req = builder.body(body).unwrap();
-
-        let args = parse_query(req.uri().query());
-        let mut data = HashMap::new();
-        if content_length > 0 {
-            data = match content_type.as_str() {
-                "application/json" => parse_json_body(req.body()),
+        conn.start_body();
+        let mut trailers = HeaderMap::new();
+        let mut multipart = None;
+        let body = if chunked {
+            // a message with both headers could be read differently by a proxy in front of us
+            if content_length.is_some() {
+                return Err(ParseError::InvalidContentLength);
+            }
+            let (body, fields) = read_chunked_body(conn, limits)?;
+            for (key, value) in fields {
+                let key = HeaderName::try_from(key).map_err(|_| ParseError::InvalidHeader)?;
+                let value = HeaderValue::try_from(value).map_err(|_| ParseError::InvalidHeader)?;
+                trailers.append(key, value);
+            }
+            body
+        } else {
+            let content_length = content_length.unwrap_or_default();
+            if content_length > limits.max_body_size {
+                return Err(ParseError::PayloadTooLarge);
+            }
+            match multipart::boundary(&content_type).filter(|_| is_multipart(&content_type)) {
+                // uploads are parsed while they are read instead of being buffered first
+                Some(boundary) if content_length > 0 => {
+                    let body = conn.take(content_length as u64);
+                    multipart = Some(multipart::parse(body, boundary, limits)?);
+                    Vec::new()
+                }
                 _ => {
-                    // TODO: support more content types
-                    warn!("unsupported content type {}", content_type);
-                    HashMap::new()
+                    let mut body = vec![0; content_length];
+                    conn.read_exact(&mut body)?;
+                    body
                 }
-            };
-        }
+            }
+        };
+        conn.clear_deadline()?;
+        let req = builder
+            .body(body)
+            .map_err(|_| ParseError::InvalidRequestLine)?;
 
-        Self {
+        let args = parse_query(req.uri().query());
+        let body = match multipart {
+            Some(multipart) => multipart,
+            None if !req.body().is_empty() => parse_body(&content_type, req.body(), limits)?,
+            None => Multipart::default(),
+        };
+
+        Ok(Self {
             req,
+            trailers,
             args,
-            data,
+            data: body.fields,
             params: HashMap::new(),
-        }
+            files: body.files,
+            urls: Arc::default(),
+            state: Arc::default(),
+        })
     }
 
     /// HTTP method for current `Request`
@@ -127,6 +420,11 @@ impl Request {
         self.req.method().as_str()
     }
 
+    /// HTTP version for current `Request`
+    pub fn version(&self) -> Version {
+        self.req.version()
+    }
+
     /// HTTP path for current `Request`
     pub fn path(&self) -> &str {
         self.req.uri().path()
@@ -145,6 +443,228 @@ impl Request {
         self.req.headers()
     }
 
+    /// Typed values attached to the `Request`, usually by a middleware for the handlers
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::sync::Arc;
+    /// use haro::{Application, DynHandler, Request, Response};
+    ///
+    /// struct User(String);
+    ///
+    /// fn auth(next: DynHandler) -> DynHandler {
+    ///     Arc::new(move |mut req: Request| {
+    ///         req.extensions_mut().insert(User("Haro".to_string()));
+    ///         next(req)
+    ///     })
+    /// }
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.middleware(auth);
+    /// app.route("/", |req: Request| {
+    ///     let user = req.extensions().get::<User>().unwrap();
+    ///     Response::str(format!("Hello {}", user.0))
+    /// });
+    ///
+    /// let res = app.request("get", "/", HashMap::new(), &[]);
+    /// assert_eq!(b"Hello Haro", res.body());
+    /// ```
+    pub fn extensions(&self) -> &Extensions {
+        self.req.extensions()
+    }
+
+    /// Mutable typed values attached to the `Request`
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.req.extensions_mut()
+    }
+
+    /// Path parameter converted into `T`
+    /// # Example
+    /// ```
+    /// use haro::{Application, Request, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.route("/users/<id:int>", user);
+    ///
+    /// fn user(req: Request) -> Response {
+    ///     match req.param::<u32>("id") {
+    ///         Ok(id) => Response::str(format!("user {id}")),
+    ///         Err(e) => e.into(),
+    ///     }
+    /// }
+    /// ```
+    pub fn param<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        let value = self
+            .params
+            .get(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse().map_err(|_| ParamError::Invalid {
+            name: name.to_string(),
+            value: value.clone(),
+        })
+    }
+
+    /// Path parameters deserialized into `T`
+    /// # Example
+    /// ```
+    /// use haro::{Application, Request, Response};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Post {
+    ///     year: u16,
+    ///     slug: String,
+    /// }
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.get("/<year:int>/<slug>", |req| match req.params::<Post>() {
+    ///     Ok(post) => Response::str(format!("{} in {}", post.slug, post.year)),
+    ///     Err(e) => e.into(),
+    /// });
+    /// ```
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        from_pairs("path parameters", &self.params)
+    }
+
+    /// Query string deserialized into `T`, repeated keys fill sequences such as `Vec<String>` and
+    /// an absent query string is treated as empty
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use haro::Request;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct Page {
+    ///     page: u32,
+    ///     per_page: Option<u32>,
+    /// }
+    ///
+    /// let req = Request::new("GET", "/?page=2", HashMap::new(), &[]);
+    /// let page: Page = req.query().unwrap();
+    /// assert_eq!((2, None), (page.page, page.per_page));
+    ///
+    /// let req = Request::new("GET", "/?page=two", HashMap::new(), &[]);
+    /// let e = req.query::<Page>().unwrap_err();
+    /// assert_eq!(Some("page"), e.field());
+    /// ```
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        from_multimap("query string", &parse_query(self.req.uri().query()))
+    }
+
+    /// JSON body deserialized into `T`
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use haro::Request;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct User {
+    ///     name: String,
+    ///     tags: Vec<String>,
+    /// }
+    ///
+    /// let body = br#"{"name": "Haro", "tags": ["robot", 42]}"#;
+    /// let req = Request::new("POST", "/", HashMap::new(), body);
+    /// let e = req.json::<User>().unwrap_err();
+    /// assert_eq!(Some("tags[1]"), e.field());
+    /// ```
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        let mut de = serde_json::Deserializer::from_slice(self.req.body());
+        serde_path_to_error::deserialize(&mut de)
+            .map_err(|e| ExtractError::from_path("json body", e))
+    }
+
+    /// Fields of an `application/x-www-form-urlencoded` or `multipart/form-data` body
+    /// deserialized into `T`
+    pub fn form<T: DeserializeOwned>(&self) -> Result<T, ExtractError> {
+        let content_type = self
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        match media_type(content_type).as_str() {
+            "application/x-www-form-urlencoded" => from_urlencoded("form", self.req.body()),
+            "multipart/form-data" => from_pairs("form", &self.data),
+            _ => Err(ExtractError::new(
+                "form",
+                None,
+                format!("unexpected content type {content_type:?}"),
+            )),
+        }
+    }
+
+    /// Build the URL of a named route, see [`Application::url_for`](crate::Application::url_for)
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, UrlError> {
+        self.urls.url_for(name, params, query)
+    }
+
+    /// Value of type `T` shared with [`Application::state`](crate::Application::state)
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use haro::{Application, Request, Response};
+    ///
+    /// struct Config {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.state(Config { name: "Haro".to_string() });
+    /// app.route("/", |req: Request| {
+    ///     let config = req.state::<Config>().unwrap();
+    ///     Response::str(format!("Hello {}", config.name))
+    /// });
+    ///
+    /// let res = app.request("get", "/", HashMap::new(), &[]);
+    /// assert_eq!(b"Hello Haro", res.body());
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get()
+    }
+
+    /// Files uploaded in a `multipart/form-data` body, by field name
+    pub fn files(&self) -> &HashMap<String, Vec<UploadedFile>> {
+        &self.files
+    }
+
+    /// First file uploaded in the field `name` of a `multipart/form-data` body
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use haro::Request;
+    ///
+    /// let headers = HashMap::from([(
+    ///     "content-type".to_string(),
+    ///     "multipart/form-data; boundary=X".to_string(),
+    /// )]);
+    /// let body = b"--X\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\r\nPNG\r\n--X--\r\n";
+    /// let req = Request::new("POST", "/", headers, body);
+    ///
+    /// let avatar = req.file("avatar").unwrap();
+    /// assert_eq!("me.png", avatar.filename());
+    /// assert_eq!(3, avatar.size());
+    /// ```
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.get(name).and_then(|files| files.first())
+    }
+
+    /// HTTP body for current `Request`
+    pub fn body(&self) -> &[u8] {
+        self.req.body()
+    }
+
+    /// HTTP trailers sent after a chunked body of current `Request`
+    pub fn trailers(&self) -> &HeaderMap<HeaderValue> {
+        &self.trailers
+    }
+
     /// HTTP cookies for current `Request`
     pub fn cookies(&self) -> HashMap<String, String> {
         let headers = self.headers();
@@ -159,4 +679,81 @@ impl Request {
 
         cookies_map
     }
+
+    /// Whether the client wants to keep the connection open after this `Request`
+    ///
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`,
+    /// HTTP/1.0 connections are closed unless the client sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.headers()
+                .get_all(CONNECTION)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .any(|v| v.trim().eq_ignore_ascii_case(token))
+        };
+        match self.version() {
+            Version::HTTP_09 | Version::HTTP_10 => has_token("keep-alive"),
+            _ => !has_token("close"),
+        }
+    }
+}
+
+/// Parse the fields and uploaded files of a body read in memory, by its content type
+fn parse_body(content_type: &str, body: &[u8], limits: &Limits) -> Result<Multipart, ParseError> {
+    let fields = match media_type(content_type).as_str() {
+        "application/json" => parse_json_body(body),
+        "application/x-www-form-urlencoded" => parse_form_body(body),
+        "multipart/form-data" => {
+            let boundary = multipart::boundary(content_type).ok_or(ParseError::InvalidMultipart)?;
+            return multipart::parse(body, boundary, limits);
+        }
+        _ => {
+            warn!("unsupported content type {}", content_type);
+            HashMap::new()
+        }
+    };
+    Ok(Multipart {
+        fields,
+        files: HashMap::new(),
+    })
+}
+
+/// Content type without its parameters, in lowercase
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn is_multipart(content_type: &str) -> bool {
+    media_type(content_type) == "multipart/form-data"
+}
+
+/// Deserialize `application/x-www-form-urlencoded` data, see [`from_multimap`]
+fn from_urlencoded<T: DeserializeOwned>(
+    source: &'static str,
+    input: &[u8],
+) -> Result<T, ExtractError> {
+    from_multimap(source, &parse_urlencoded(&String::from_utf8_lossy(input)))
+}
+
+fn from_pairs<T: DeserializeOwned>(
+    source: &'static str,
+    pairs: &HashMap<String, String>,
+) -> Result<T, ExtractError> {
+    from_multimap(source, &pairs.iter().collect())
+}
+
+/// Deserialize the values parsed into the types of the fields, repeated keys fill sequences
+fn from_multimap<T: DeserializeOwned>(
+    source: &'static str,
+    map: &MultiMap,
+) -> Result<T, ExtractError> {
+    serde_path_to_error::deserialize(map.deserializer())
+        .map_err(|e| ExtractError::from_path(source, e))
 }
@@ -1,21 +1,30 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
 
 use http::{
-    header::{HeaderName, CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
-    HeaderValue, Response as HttpResponse, StatusCode,
+    header::{HeaderName, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, LOCATION, TRANSFER_ENCODING},
+    Extensions, HeaderMap, HeaderValue, Response as HttpResponse, StatusCode,
 };
-use log::error;
 use serde::Serialize;
 
+use crate::http::body::Body;
+
 #[cfg(feature = "template")]
 use crate::template::TEMPLATES;
 #[cfg(feature = "template")]
+use log::error;
+#[cfg(feature = "template")]
 use tera::Context;
 
 /// HTTP Response
 #[derive(Debug)]
 pub struct Response {
-    res: HttpResponse<Vec<u8>>,
+    res: HttpResponse<Body>,
 }
 
 impl Response {
@@ -36,21 +45,120 @@ impl Response {
             builder = builder.header(key, val);
         }
 
-        let res = builder
-            .header(CONTENT_LENGTH, body.len())
-            .body(body.to_vec())
-            .unwrap();
+        // responses without content must not carry a length
+        if !(status.is_informational() || status == StatusCode::NO_CONTENT) {
+            builder = builder.header(CONTENT_LENGTH, body.len());
+        }
+        let res = builder.body(Body::Bytes(body.to_vec())).unwrap();
         Self { res }
     }
 
+    /// Create a `Response` with a binary body such as an image or a protobuf message
+    /// # Example
+    /// ```
+    /// use haro::Response;
+    ///
+    /// let res = Response::bytes("image/png", vec![0x89, b'P', b'N', b'G']);
+    /// assert_eq!(&[0x89, b'P', b'N', b'G'], res.body());
+    /// ```
+    pub fn bytes(content_type: &str, body: Vec<u8>) -> Self {
+        let length = body.len() as u64;
+        Self::with_body(content_type, Body::Bytes(body), Some(length))
+    }
+
+    /// Create a `Response` streaming the body from a reader, the body is sent in chunks
+    /// without being buffered in memory
+    /// # Example
+    /// ```
+    /// use std::io::Cursor;
+    /// use haro::Response;
+    ///
+    /// let res = Response::stream("text/csv", Cursor::new("id,name\n1,Haro\n"));
+    /// ```
+    pub fn stream<R>(content_type: &str, reader: R) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        Self::with_body(content_type, Body::Reader(Box::new(reader)), None)
+    }
+
+    /// Create a `Response` from an iterator of chunks, each chunk is written to the connection
+    /// as soon as it is produced
+    /// # Example
+    /// ```
+    /// use haro::Response;
+    ///
+    /// let rows = (0..3).map(|i| format!("{i},Haro\n").into_bytes());
+    /// let res = Response::chunks("text/csv", rows);
+    /// ```
+    pub fn chunks<I>(content_type: &str, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        let body = Body::Chunks(Box::new(chunks.into_iter()));
+        Self::with_body(content_type, body, None)
+    }
+
+    /// Create a `Response` sending a file, the content type is guessed from the extension
+    /// # Example
+    /// ```no_run
+    /// use haro::Response;
+    ///
+    /// let res = Response::file("templates/index.html").unwrap();
+    /// ```
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let content_type = guess_content_type(path);
+        Ok(Self::with_body(
+            content_type,
+            Body::File(file),
+            Some(length),
+        ))
+    }
+
+    fn with_body(content_type: &str, body: Body, length: Option<u64>) -> Self {
+        let builder = HttpResponse::builder().header(CONTENT_TYPE, content_type);
+        let builder = match length {
+            Some(length) => builder.header(CONTENT_LENGTH, length),
+            None => builder.header(TRANSFER_ENCODING, "chunked"),
+        };
+        Self {
+            res: builder.body(body).unwrap(),
+        }
+    }
+
     /// Returns status code of the `Response`
     pub fn status(&self) -> StatusCode {
         self.res.status()
     }
 
-    /// Returns body of the `Response`
+    /// Returns headers of the `Response`
+    pub fn headers(&self) -> &HeaderMap<HeaderValue> {
+        self.res.headers()
+    }
+
+    /// Typed values attached to the `Response`, usually by a handler for the middlewares
+    pub fn extensions(&self) -> &Extensions {
+        self.res.extensions()
+    }
+
+    /// Mutable typed values attached to the `Response`
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.res.extensions_mut()
+    }
+
+    /// Returns body of the `Response`, empty for a body streamed from a reader, an iterator or
+    /// a file, use [`Response::into_body`] to read those
     pub fn body(&self) -> &[u8] {
-        self.res.body()
+        self.res.body().as_bytes().unwrap_or_default()
+    }
+
+    /// Consumes the `Response` and returns its [`Body`]
+    pub fn into_body(self) -> Body {
+        self.res.into_body()
     }
 
     /// Set a new header, generate and return a new `Response`
@@ -74,6 +182,104 @@ impl Response {
         }
     }
 
+    /// Attach a typed value, replacing the value of the same type, and return the `Response`
+    /// # Example
+    /// ```
+    /// use haro::Response;
+    ///
+    /// struct CacheHit(bool);
+    ///
+    /// let res = Response::str("Hello Haro").extension(CacheHit(true));
+    /// assert!(res.extensions().get::<CacheHit>().unwrap().0);
+    /// ```
+    pub fn extension<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.res.extensions_mut().insert(value);
+        self
+    }
+
+    /// Set the status code, generate and return a new `Response`
+    /// # Example
+    /// ```
+    /// use haro::Response;
+    /// use http::StatusCode;
+    ///
+    /// let res = Response::str("Nothing here").with_status(StatusCode::NOT_FOUND);
+    /// assert_eq!(StatusCode::NOT_FOUND, res.status());
+    /// ```
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        *self.res.status_mut() = status;
+        self
+    }
+
+    /// Send the body with `Transfer-Encoding: chunked` instead of a `Content-Length`
+    /// # Example
+    /// ```
+    /// use haro::Response;
+    ///
+    /// let res = Response::str("Hello Haro").chunked();
+    /// assert!(res.is_chunked());
+    /// ```
+    pub fn chunked(self) -> Self {
+        let (mut parts, body) = self.res.into_parts();
+        parts.headers.remove(CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        Self {
+            res: HttpResponse::from_parts(parts, body),
+        }
+    }
+
+    /// Whether the body is sent with `Transfer-Encoding: chunked`
+    pub fn is_chunked(&self) -> bool {
+        self.res
+            .headers()
+            .get(TRANSFER_ENCODING)
+            .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"chunked"))
+    }
+
+    /// Send the body without chunks for clients that don't understand them, with a
+    /// `Content-Length` when the body is in memory or by closing the connection otherwise
+    pub(crate) fn unchunked(self) -> Self {
+        let (mut parts, body) = self.res.into_parts();
+        parts.headers.remove(TRANSFER_ENCODING);
+        match body.as_bytes() {
+            Some(bytes) => parts.headers.insert(CONTENT_LENGTH, bytes.len().into()),
+            None => parts
+                .headers
+                .insert(CONNECTION, HeaderValue::from_static("close")),
+        };
+        Self {
+            res: HttpResponse::from_parts(parts, body),
+        }
+    }
+
+    /// Drop the body but keep the headers describing it, as in a response to `HEAD`
+    pub(crate) fn without_body(self) -> Self {
+        let (parts, _) = self.res.into_parts();
+        Self {
+            res: HttpResponse::from_parts(parts, Body::Bytes(Vec::new())),
+        }
+    }
+
+    /// Write the status line, headers and body into `w`, streaming the body incrementally,
+    /// the body is left out if `with_body` is not set
+    pub(crate) fn write_to<W: Write>(self, w: &mut W, with_body: bool) -> io::Result<()> {
+        let chunked = self.is_chunked();
+        let (parts, body) = self.res.into_parts();
+        write!(w, "{:?} {}\r\n", parts.version, parts.status)?;
+        for (key, val) in &parts.headers {
+            write!(w, "{key}: ")?;
+            w.write_all(val.as_bytes())?;
+            w.write_all(b"\r\n")?;
+        }
+        w.write_all(b"\r\n")?;
+        if !with_body {
+            return Ok(());
+        }
+        body.write_to(w, chunked)
+    }
+
     /// Generate plain text response
     /// # Example
     /// ```
@@ -133,21 +339,23 @@ impl Response {
     }
 }
 
+/// Human readable form of the `Response`, binary bodies are shown lossily and streamed bodies
+/// are omitted, the response is sent to clients byte by byte instead
 impl Display for Response {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // write version and status
         let version = self.res.version();
         let status = self.res.status();
-        write!(f, "{version:?} {status}\r\n").unwrap();
+        write!(f, "{version:?} {status}\r\n")?;
 
         // write headers
         for (key, val) in self.res.headers() {
-            let val = std::str::from_utf8(val.as_bytes()).unwrap();
-            write!(f, "{key}: {val}\r\n").unwrap();
+            let val = String::from_utf8_lossy(val.as_bytes());
+            write!(f, "{key}: {val}\r\n")?;
         }
 
         // write body
-        write!(f, "\r\n{}", std::str::from_utf8(self.res.body()).unwrap())
+        write!(f, "\r\n{}", String::from_utf8_lossy(self.body()))
     }
 }
 
@@ -162,3 +370,91 @@ pub fn redirect(location: &str, permanently: bool) -> Response {
     let headers = HashMap::from([(LOCATION, location)]);
     Response::new(status, &body, headers)
 }
+
+/// Guess the content type of a file from its extension
+fn guess_content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "xml" => "application/xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Conversion into a [`Response`], implemented by the values returned from handlers
+/// # Example
+/// ```
+/// use haro::{Application, Error, Request};
+/// use http::StatusCode;
+/// use serde_json::{json, Value};
+///
+/// let mut app = Application::new("0:8080");
+/// app.get("/", |_| "Hello Haro");
+/// app.post("/users", |_| (StatusCode::CREATED, json!({"id": 1})));
+/// app.get("/users/<id:int>", user);
+///
+/// fn user(req: Request) -> Result<Value, Error> {
+///     let id: u32 = req.param("id")?;
+///     match id {
+///         1 => Ok(json!({"id": id, "name": "Haro"})),
+///         _ => Err(Error::json(StatusCode::NOT_FOUND, json!({"error": "no such user"}))),
+///     }
+/// }
+/// ```
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for &str {
+    fn into_response(self) -> Response {
+        Response::str(self)
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::str(self)
+    }
+}
+
+impl IntoResponse for serde_json::Value {
+    fn into_response(self) -> Response {
+        Response::json(self)
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+    fn into_response(self) -> Response {
+        self.1.into_response().with_status(self.0)
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
@@ -1,42 +1,174 @@
 use std::collections::HashMap;
+use std::io::{BufRead, Read};
 
 use log::warn;
+use percent_encoding::{percent_decode_str, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use serde_json::Value;
 
-use crate::http::conn::Conn;
+use crate::http::{
+    conn::Conn,
+    multimap::MultiMap,
+    request::{Limits, ParseError},
+};
 
-pub fn read_headers(conn: &mut Conn) -> HashMap<String, String> {
-    //TODO: limit headers
-    let mut headers = HashMap::new();
+/// Characters escaped in a path segment
+pub const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'\'')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters escaped in a query string key or value
+pub const QUERY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Header or trailer fields in the order they were received
+pub type Fields = Vec<(String, String)>;
+
+/// Longest chunk size line accepted, including chunk extensions
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+/// Read a line terminated by `\n`, failing with `too_long` when it doesn't fit in `limit` bytes
+pub fn read_line<R: BufRead>(
+    r: &mut R,
+    limit: usize,
+    too_long: ParseError,
+) -> Result<String, ParseError> {
+    if limit == 0 {
+        return Err(too_long);
+    }
+    let mut buf = String::new();
+    let n = r.take(limit as u64).read_line(&mut buf)?;
+    if n == 0 {
+        return Err(ParseError::ConnectionClosed);
+    }
+    if !buf.ends_with('\n') {
+        return Err(if n >= limit {
+            too_long
+        } else {
+            ParseError::ConnectionClosed
+        });
+    }
+    Ok(buf)
+}
+
+pub fn read_headers<R: BufRead>(r: &mut R, limits: &Limits) -> Result<Fields, ParseError> {
+    let mut headers = Vec::new();
+    let mut remaining = limits.max_header_size;
     loop {
-        let mut buf = String::new();
-        conn.read_line(&mut buf);
-        if buf == "\r\n" {
+        let buf = read_line(r, remaining, ParseError::HeadersTooLarge)?;
+        remaining -= buf.len();
+        if buf == "\r\n" || buf == "\n" {
             break;
         }
-        let header: Vec<&str> = buf.splitn(2, ':').collect();
-        if header.len() != 2 {
-            warn!("failed to parse header: {:?}", header);
+        // folded lines and whitespace before the colon could be read differently by a proxy in
+        // front, which would let a request be smuggled past it
+        let Some((key, value)) = buf.split_once(':') else {
+            warn!("failed to parse header: {:?}", buf);
+            return Err(ParseError::InvalidHeader);
+        };
+        if buf.starts_with([' ', '\t']) || key.ends_with([' ', '\t']) {
+            warn!("invalid whitespace in header: {:?}", buf);
+            return Err(ParseError::InvalidHeader);
+        }
+        headers.push((key.to_string(), value.trim().to_string()));
+        if headers.len() > limits.max_headers {
+            return Err(ParseError::HeadersTooLarge);
         }
-        headers.insert(header[0].trim().to_string(), header[1].trim().to_string());
     }
-    headers
+    Ok(headers)
 }
 
-pub fn parse_query(query: Option<&str>) -> HashMap<String, String> {
-    let mut args = HashMap::new();
-    if let Some(query) = query {
-        for q in query.split('&') {
-            let qs: Vec<&str> = q.split('=').collect();
-            if qs.len() != 2 {
-                warn!("failed to parse query string: {:?}", qs);
-                continue;
-            }
-            args.insert(qs[0].to_string(), qs[1].to_string());
+/// Decode a `Transfer-Encoding: chunked` body, returns the body and the trailer fields
+pub fn read_chunked_body(
+    conn: &mut Conn,
+    limits: &Limits,
+) -> Result<(Vec<u8>, Fields), ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(conn, MAX_CHUNK_SIZE_LINE, ParseError::InvalidChunk)?;
+        // chunk extensions after `;` are ignored, the size itself is only hex digits since signs
+        // or spaces accepted here but not by a proxy in front would let a request be smuggled
+        let line = line.trim_end_matches(['\r', '\n']);
+        let size = line.split(';').next().unwrap_or_default();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidChunk);
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::InvalidChunk)?;
+        if size == 0 {
+            break;
+        }
+        // compared this way round so a huge size can't overflow
+        if size > limits.max_body_size.saturating_sub(body.len()) {
+            return Err(ParseError::PayloadTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        conn.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        conn.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ParseError::InvalidChunk);
         }
     }
-    args
+    let trailers = read_headers(conn, limits)?;
+    Ok((body, trailers))
+}
+
+/// Decode the arguments of a query string, keeping repeated keys and empty values
+pub fn parse_query(query: Option<&str>) -> MultiMap {
+    query.map(parse_urlencoded).unwrap_or_default()
 }
 
+/// Decode an `application/x-www-form-urlencoded` body, later values replace earlier ones
+pub fn parse_form_body(body: &[u8]) -> HashMap<String, String> {
+    parse_urlencoded(&String::from_utf8_lossy(body))
+        .into_iter()
+        .collect()
+}
+
+/// Decode `application/x-www-form-urlencoded` pairs, `+` stands for a space and a key without
+/// `=` has an empty value
+pub fn parse_urlencoded(s: &str) -> MultiMap {
+    let decode = |s: &str| {
+        percent_decode_str(&s.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    s.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// Top level fields of a JSON object body, values other than strings are kept as JSON text
 pub fn parse_json_body(body: &[u8]) -> HashMap<String, String> {
-    serde_json::from_slice(body).unwrap()
+    let object: serde_json::Map<String, Value> = match serde_json::from_slice(body) {
+        Ok(object) => object,
+        Err(e) => {
+            warn!("failed to parse json body: {}", e);
+            return HashMap::new();
+        }
+    };
+    object
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(s) => (key, s),
+            value => (key, value.to_string()),
+        })
+        .collect()
 }
@@ -10,7 +10,9 @@
 //!   - Post data
 //!   - JSON
 //!   - Cookie
+//!   - Streaming body
 //! - Middleware
+//! - Blueprint
 //! - Template (optional)
 //! - Database (optional)
 //! - Tests
@@ -56,15 +58,26 @@
 //! [examples]: https://github.com/shellfly/haro/tree/main/examples
 //!
 mod app;
+mod blueprint;
+mod error;
 mod http;
 pub mod middleware;
 mod pool;
 mod router;
+mod shutdown;
+mod state;
 
 pub use crate::app::Application;
-pub use crate::http::request::Request;
-pub use crate::http::response::{redirect, Response};
-pub use crate::router::{DynHandler, Handler};
+pub use crate::blueprint::Blueprint;
+pub use crate::error::Error;
+pub use crate::http::body::Body;
+pub use crate::http::multimap::MultiMap;
+pub use crate::http::multipart::UploadedFile;
+pub use crate::http::request::{ExtractError, ParamError, ParseError, Request};
+pub use crate::http::response::{redirect, IntoResponse, Response};
+pub use crate::router::{DynHandler, Handler, Route, UrlError};
+pub use crate::shutdown::{ServerHandle, ShutdownHandle};
+pub use ::http::Extensions;
 
 #[cfg(feature = "template")]
 mod template;
@@ -1,10 +1,19 @@
 //! Middleware definition and built-in middlewares
 //!
 //!
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use http::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    VARY,
+};
+use http::{Method, StatusCode};
 use log::info;
+use regex::Regex;
 
 use crate::{DynHandler, Request, Response};
 
@@ -33,3 +42,220 @@ pub fn logging(next: DynHandler) -> DynHandler {
         res
     })
 }
+
+/// Build a CORS configuration registered with [`Application::cors`](crate::Application::cors),
+/// see [`Cors`]
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use haro::{Application, middleware};
+/// use http::Method;
+///
+/// let mut app = Application::new("0:8080");
+/// let cors = middleware::cors()
+///     .allow_origin("https://app.example.com")
+///     .allow_origin_regex(r"^https://[a-z]+\.example\.com$")
+///     .allow_methods(&[Method::GET, Method::POST])
+///     .allow_headers(&["content-type", "authorization"])
+///     .expose_headers(&["x-request-id"])
+///     .allow_credentials(true)
+///     .max_age(Duration::from_secs(3600));
+/// app.cors(cors);
+/// ```
+pub fn cors() -> Cors {
+    Cors::default()
+}
+
+/// Builder of the `Access-Control-*` headers added to the responses
+///
+/// Preflight requests from an allowed origin are answered with `204 No Content` before the
+/// `before_request` hooks run, so they don't need credentials. The other responses to an allowed
+/// origin get the CORS headers once the error handlers and `after_request` hooks are done, so
+/// error pages carry them too. Requests without an `Origin` header or from another origin get no
+/// CORS headers.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Vec<Origin>,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    exposed: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+enum Origin {
+    Any,
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            headers: Vec::new(),
+            exposed: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Allow requests from `origin`, `*` allows every origin
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.origins.push(match origin {
+            "*" => Origin::Any,
+            _ => Origin::Exact(origin.to_string()),
+        });
+        self
+    }
+
+    /// Allow requests from the origins matching `pattern`
+    ///
+    /// # Panics
+    /// Panics if `pattern` is not a valid regex
+    pub fn allow_origin_regex(mut self, pattern: &str) -> Self {
+        self.origins
+            .push(Origin::Regex(Regex::new(pattern).unwrap()));
+        self
+    }
+
+    /// Methods allowed in preflight requests, `GET`, `HEAD`, `POST`, `PUT`, `PATCH` and `DELETE` by default
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Request headers allowed in preflight requests, `*` allows every requested header
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Response headers the browser exposes to scripts
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.exposed = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Allow requests with cookies and credentials, the origin is then always echoed instead of `*`
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// How long the browser may cache the result of a preflight request
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Origin the response to `req` is decorated for and the answer to `req` if it is an allowed
+    /// preflight request, the preflight requests that aren't allowed get no CORS headers
+    pub(crate) fn start(&self, req: &Request) -> (Option<HeaderValue>, Option<Response>) {
+        let origin = req.headers().get(ORIGIN).cloned();
+        let headers = req.headers();
+        if req.method() != Method::OPTIONS || !headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD) {
+            return (origin, None);
+        }
+        match self.preflight(req) {
+            Some(res) => (origin, Some(res)),
+            None => (None, None),
+        }
+    }
+
+    /// Answer a preflight request from an allowed origin for an allowed method and headers
+    fn preflight(&self, req: &Request) -> Option<Response> {
+        let origin = req.headers().get(ORIGIN)?.to_str().ok()?;
+        if !self.allows(origin) {
+            return None;
+        }
+        let method = req.headers().get(ACCESS_CONTROL_REQUEST_METHOD)?;
+        let method = Method::from_bytes(method.as_bytes()).ok()?;
+        if !self.methods.contains(&method) {
+            return None;
+        }
+
+        let requested = req
+            .headers()
+            .get(ACCESS_CONTROL_REQUEST_HEADERS)
+            .map(|h| h.to_str().unwrap_or_default())
+            .unwrap_or_default();
+        let any_header = self.headers.iter().any(|h| h == "*");
+        let allowed = requested
+            .split(',')
+            .map(|h| h.trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .all(|h| any_header || self.headers.contains(&h));
+        if !allowed {
+            return None;
+        }
+
+        let methods: Vec<&str> = self.methods.iter().map(Method::as_str).collect();
+        let mut res = Response::new(StatusCode::NO_CONTENT, &[], HashMap::new())
+            .header(ACCESS_CONTROL_ALLOW_METHODS, methods.join(", "))
+            .header(
+                VARY,
+                "Access-Control-Request-Method, Access-Control-Request-Headers",
+            );
+        // a wildcard is only understood without credentials, so echo the requested headers
+        let headers = if any_header {
+            requested.to_string()
+        } else {
+            self.headers.join(", ")
+        };
+        if !headers.is_empty() {
+            res = res.header(ACCESS_CONTROL_ALLOW_HEADERS, headers);
+        }
+        if let Some(max_age) = self.max_age {
+            res = res.header(ACCESS_CONTROL_MAX_AGE, max_age.as_secs());
+        }
+        Some(res)
+    }
+
+    /// Add the CORS headers to the response of a request from `origin`
+    pub(crate) fn decorate(
+        &self,
+        mut res: Response,
+        origin: Option<&HeaderValue>,
+        preflight: bool,
+    ) -> Response {
+        let any_origin = self.origins.iter().any(|o| matches!(o, Origin::Any));
+        let echo = !any_origin || self.credentials;
+        // the headers depend on the origin, so caches must not share the response between origins
+        if echo {
+            res = res.header(VARY, "Origin");
+        }
+        let origin = origin.and_then(|origin| origin.to_str().ok());
+        let Some(origin) = origin.filter(|origin| self.allows(origin)) else {
+            return res;
+        };
+
+        res = res.header(ACCESS_CONTROL_ALLOW_ORIGIN, if echo { origin } else { "*" });
+        if self.credentials {
+            res = res.header(ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        }
+        if !preflight && !self.exposed.is_empty() {
+            res = res.header(ACCESS_CONTROL_EXPOSE_HEADERS, self.exposed.join(", "));
+        }
+        res
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|o| match o {
+            Origin::Any => true,
+            Origin::Exact(exact) => exact == origin,
+            Origin::Regex(regex) => regex.is_match(origin),
+        })
+    }
+}
@@ -1,87 +1,198 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    any::Any,
+    sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError},
     thread,
+    time::{Duration, Instant},
 };
 
-use log::debug;
+use log::{debug, error};
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// How often a worker is checked while waiting for it to finish with a deadline
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Job = Box<dyn Task>;
+type Receiver = Arc<Mutex<mpsc::Receiver<Job>>>;
+/// Thread of each worker, by worker id
+type Threads = Arc<Mutex<Vec<Option<thread::JoinHandle<()>>>>>;
+
+/// A job queued in the pool, it can be taken back as its concrete type when the queue is full
+trait Task: Send {
+    fn run(self: Box<Self>);
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+/// Call of `f` with `arg` on a worker
+struct Call<T, F> {
+    arg: T,
+    f: F,
+}
+
+impl<T, F> Task for Call<T, F>
+where
+    T: Send + 'static,
+    F: FnOnce(T) + Send + 'static,
+{
+    fn run(self: Box<Self>) {
+        (self.f)(self.arg)
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
 
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    threads: Threads,
+    sender: Option<mpsc::SyncSender<Job>>,
 }
 
 impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
+    /// Start `size` workers taking jobs from a queue holding up to `capacity` jobs, with no room
+    /// left a job is only handed over to an idle worker
+    pub fn new(size: usize, capacity: usize) -> ThreadPool {
         assert!(size > 0);
-        debug!("new thread pool with size: {size}");
-        let (sender, receiver) = mpsc::channel();
+        debug!("new thread pool with size: {size}, queue capacity: {capacity}");
+        let (sender, receiver) = mpsc::sync_channel(capacity);
 
         let receiver = Arc::new(Mutex::new(receiver));
-
-        let mut workers = Vec::with_capacity(size);
+        let threads: Threads = Arc::new(Mutex::new((0..size).map(|_| None).collect()));
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            spawn_worker(id, Arc::clone(&receiver), Arc::clone(&threads));
         }
 
         ThreadPool {
-            workers,
+            threads,
             sender: Some(sender),
         }
     }
 
+    /// Queue `f`, waiting for room in the queue when it is full
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
+        let job = Box::new(Call {
+            arg: (),
+            f: move |()| f(),
+        });
 
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
+
+    /// Queue a call of `f` with `arg` if there is room in the queue, otherwise give `arg` back
+    pub fn try_execute<T, F>(&self, arg: T, f: F) -> Result<(), T>
+    where
+        T: Send + 'static,
+        F: FnOnce(T) + Send + 'static,
+    {
+        let job: Job = Box::new(Call { arg, f });
+
+        match self.sender.as_ref().unwrap().try_send(job) {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Full(job) | mpsc::TrySendError::Disconnected(job)) => {
+                let call = job.into_any().downcast::<Call<T, F>>().unwrap();
+                Err(call.arg)
+            }
+        }
+    }
 }
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
+impl ThreadPool {
+    /// Stop taking new jobs and wait up to `timeout` for the workers to finish the queued ones,
+    /// returns false if some workers are still busy, they are then left running detached
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        self.join(Some(Instant::now() + timeout))
+    }
+
+    fn join(&mut self, deadline: Option<Instant>) -> bool {
         drop(self.sender.take());
 
-        for worker in &mut self.workers {
-            debug!("Shutting down worker {}", worker.id);
+        let size = lock(&self.threads).len();
+        for id in 0..size {
+            debug!("Shutting down worker {id}");
 
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+            // a worker dying meanwhile is replaced by a new thread, join it too
+            loop {
+                let thread = lock(&self.threads)[id].take();
+                let Some(thread) = thread else {
+                    break;
+                };
+                if let Some(deadline) = deadline {
+                    while !thread.is_finished() {
+                        if Instant::now() >= deadline {
+                            // detach every busy worker so dropping the pool doesn't wait for them
+                            lock(&self.threads)
+                                .iter_mut()
+                                .for_each(|thread| drop(thread.take()));
+                            return false;
+                        }
+                        thread::sleep(JOIN_POLL_INTERVAL);
+                    }
+                }
+                let _ = thread.join();
             }
         }
+        true
     }
 }
 
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.join(None);
+    }
 }
 
-impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
+/// Start the thread of worker `id`, it is started again if it panics
+fn spawn_worker(id: usize, receiver: Receiver, threads: Threads) {
+    // hold the lock so a thread dying right away can't be replaced before it is recorded
+    let mut guard = lock(&threads);
+    let sentinel = Sentinel {
+        id,
+        receiver: Arc::clone(&receiver),
+        threads: Arc::clone(&threads),
+    };
+    let thread = thread::spawn(move || {
+        let _sentinel = sentinel;
+        loop {
             let message = receiver.lock().unwrap().recv();
 
             match message {
                 Ok(job) => {
                     debug!("Worker {id} got a job; executing.");
 
-                    job();
+                    job.run();
                 }
                 Err(_) => {
                     debug!("Worker {id} disconnected; shutting down.");
                     break;
                 }
             }
-        });
+        }
+    });
+    guard[id] = Some(thread);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-        Worker {
-            id,
-            thread: Some(thread),
+/// Respawn the worker when its thread unwinds
+struct Sentinel {
+    id: usize,
+    receiver: Receiver,
+    threads: Threads,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            error!("Worker {} panicked; respawning.", self.id);
+            spawn_worker(
+                self.id,
+                Arc::clone(&self.receiver),
+                Arc::clone(&self.threads),
+            );
         }
     }
 }
@@ -2,15 +2,29 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::sync::Arc;
 
-use http::StatusCode;
+use http::header::ALLOW;
+use http::{Method, StatusCode};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
 use regex::Regex;
 
 use crate::http::request::Request;
-use crate::http::response::Response;
+use crate::http::response::{IntoResponse, Response};
+use crate::http::utils::{QUERY, SEGMENT};
+use crate::middleware::Middleware;
+use crate::state::State;
 
 /// Arc of trait object for route Handler type
 pub type DynHandler = Arc<dyn Fn(Request) -> Response + Send + Sync>;
 
+/// Wrap a function returning any [`IntoResponse`] into a [`DynHandler`]
+pub(crate) fn handler<F, R>(f: F) -> DynHandler
+where
+    F: Fn(Request) -> R + Send + Sync + 'static,
+    R: IntoResponse,
+{
+    Arc::new(move |req: Request| -> Response { f(req).into_response() })
+}
+
 pub trait Handler {
     fn handler(self) -> DynHandler
     where
@@ -21,60 +35,713 @@ pub trait Handler {
     fn call(&self, req: Request) -> Response;
 }
 
-#[derive(Default, Clone)]
+/// Routes stored in a compressed prefix tree
+///
+/// Lookup walks the path once regardless of how many routes are registered. At every node
+/// static children take precedence over parameters, constrained parameters (`int`, `uuid`,
+/// inline regexes) over `str` ones, and parameters over `path` wildcards. Routes equally
+/// specific are tried in registration order, registering the same pattern again replaces
+/// the handlers of the given methods.
+#[derive(Clone)]
 pub struct Router {
-    routes: Vec<(Rule, DynHandler)>,
+    root: Node,
+    /// Handlers for paths matching no route, by path prefix
+    fallbacks: Vec<(String, DynHandler)>,
+    /// Handler answering `OPTIONS` and `405 Method Not Allowed` for paths matching a route
+    not_allowed: DynHandler,
+    /// Values shared with every request
+    pub(crate) state: Arc<State>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+            fallbacks: vec![(String::new(), Arc::new(not_found))],
+            not_allowed: Arc::new(not_allowed),
+            state: Arc::default(),
+        }
+    }
+}
+
+/// Methods allowed for the path of a request answered by the `not_allowed` handler
+struct Allow(String);
+
+/// Handlers registered for a rule, by method or for any method
+#[derive(Default, Clone)]
+struct Endpoint {
+    any: Option<DynHandler>,
+    methods: Vec<(Method, DynHandler)>,
+}
+
+impl Endpoint {
+    fn insert(&mut self, methods: &[Method], handler: DynHandler) {
+        if methods.is_empty() {
+            self.any = Some(handler);
+            return;
+        }
+        for method in methods {
+            self.methods.retain(|(m, _)| m != method);
+            self.methods.push((method.clone(), handler.clone()));
+        }
+    }
+
+    fn get(&self, method: &str) -> Option<&DynHandler> {
+        let find = |method: &str| {
+            self.methods
+                .iter()
+                .find(|(m, _)| m.as_str() == method)
+                .map(|(_, h)| h)
+        };
+        let handler = match method {
+            // HEAD is answered by the GET handler, the body is dropped when the response is sent
+            "HEAD" => find(method).or_else(|| find("GET")),
+            _ => find(method),
+        };
+        handler.or(self.any.as_ref())
+    }
+
+    fn handlers_mut(&mut self, f: &mut dyn FnMut(&mut DynHandler)) {
+        self.any.iter_mut().for_each(&mut *f);
+        self.methods.iter_mut().for_each(|(_, h)| f(h));
+    }
+
+    /// Methods accepted by this endpoint, `None` if it accepts any method
+    fn allowed(&self) -> Option<Vec<&str>> {
+        if self.any.is_some() {
+            return None;
+        }
+        let mut allowed: Vec<&str> = self.methods.iter().map(|(m, _)| m.as_str()).collect();
+        if allowed.contains(&"GET") {
+            allowed.push("HEAD");
+        }
+        allowed.push("OPTIONS");
+        Some(allowed)
+    }
 }
 
 impl Router {
-    pub fn add<F>(&mut self, pattern: &'static str, f: F)
+    pub fn add<F, R>(&mut self, methods: &[Method], pattern: &str, f: F)
     where
-        F: Fn(Request) -> Response + Send + Sync + 'static,
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
     {
-        let rule = Rule::from(pattern);
-        let handler = Arc::new(f);
-        self.routes.push((rule, handler));
-        self.update_order()
+        self.insert(methods, pattern, handler(f))
     }
 
-    pub fn add_handler<H>(&mut self, pattern: &'static str, h: H)
+    pub fn add_handler<H>(&mut self, methods: &[Method], pattern: &str, h: H)
     where
         H: Handler + Send + Sync + 'static,
     {
+        self.insert(methods, pattern, h.handler())
+    }
+
+    pub fn insert(&mut self, methods: &[Method], pattern: &str, handler: DynHandler) {
         let rule = Rule::from(pattern);
-        let handler = h.handler();
-        self.routes.push((rule, handler));
-        self.update_order()
+        let tokens = rule.tokens.clone();
+        let route = self.root.insert(&tokens, rule);
+        route.endpoint.insert(methods, handler);
     }
 
-    fn update_order(&mut self) {
-        // TODO: compare performance with a radix tree
-        self.routes
-            .sort_by(|a, b| b.0.num_parts.cmp(&a.0.num_parts));
+    /// Answer the paths under `prefix` matching no route with `handler`, the longest prefix
+    /// wins and an empty prefix covers every path
+    pub fn fallback(&mut self, prefix: &str, handler: DynHandler) {
+        let prefix = prefix.trim_end_matches('/');
+        self.fallbacks.retain(|(p, _)| p != prefix);
+        self.fallbacks.push((prefix.to_string(), handler));
     }
 
-    pub fn dispatch(&self, path: &str) -> (HashMap<String, String>, DynHandler) {
-        for (rule, handler) in &self.routes {
-            if let Some(params) = rule._match(path) {
-                return (params, handler.clone());
+    /// Wrap every handler with `middlewares`, the first middleware being the outermost, so a
+    /// request doesn't build the chain again
+    pub fn wrap(&mut self, middlewares: &[Middleware]) {
+        let mut wrap = |handler: &mut DynHandler| {
+            for middleware in middlewares.iter().rev() {
+                *handler = middleware(handler.clone());
             }
+        };
+        self.root.handlers_mut(&mut wrap);
+        for (_, handler) in &mut self.fallbacks {
+            wrap(handler);
         }
-        (HashMap::new(), Arc::new(not_found))
+        wrap(&mut self.not_allowed);
+    }
+
+    /// Find the handler for a request and set its path parameters, falling back to the
+    /// fallback handlers or generated `404 Not Found`, `405 Method Not Allowed` and `OPTIONS`
+    /// responses
+    pub fn dispatch(&self, req: &mut Request) -> DynHandler {
+        let (method, path) = (req.method(), req.path());
+        let mut found = None;
+        let mut allowed = Vec::new();
+        let mut matched = false;
+        self.root
+            .lookup(path, &mut Vec::new(), &mut |route, params| {
+                if let Some(handler) = route.endpoint.get(method) {
+                    let params = params
+                        .iter()
+                        .map(|(k, v)| (k.clone(), percent_decode_str(v).decode_utf8_lossy().into()))
+                        .collect();
+                    found = Some((params, handler.clone()));
+                    return true;
+                }
+                matched = true;
+                for m in route.endpoint.allowed().unwrap_or_default() {
+                    if !allowed.contains(&m) {
+                        allowed.push(m);
+                    }
+                }
+                false
+            });
+        if let Some((params, handler)) = found {
+            req.params = params;
+            return handler;
+        }
+        if !matched {
+            let fallback = self
+                .fallbacks
+                .iter()
+                .filter(|(prefix, _)| {
+                    path.strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+                })
+                .max_by_key(|(prefix, _)| prefix.len());
+            return match fallback {
+                Some((_, handler)) => handler.clone(),
+                None => Arc::new(not_found),
+            };
+        }
+
+        let allow = Allow(allowed.join(", "));
+        req.extensions_mut().insert(allow);
+        self.not_allowed.clone()
     }
 }
 
 impl Display for Router {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let v: Vec<String> = self.routes.iter().map(|r| r.0.to_string()).collect();
+        let mut routes = Vec::new();
+        self.root.routes(&mut routes);
+        routes.sort_by(|a, b| a.rule.pattern.cmp(&b.rule.pattern));
+        let v: Vec<String> = routes
+            .iter()
+            .map(|route| match route.endpoint.allowed() {
+                Some(allowed) => format!("{} {}", allowed.join(","), route.rule),
+                None => format!("* {}", route.rule),
+            })
+            .collect();
         write!(f, "{}", v.join("\n"))
     }
 }
 
+/// Named rules used to build URLs
+#[derive(Debug, Default, Clone)]
+pub struct Urls {
+    rules: HashMap<String, Rule>,
+}
+
+impl Urls {
+    /// Name the rule of `pattern`, naming another rule with the same name replaces it
+    pub fn insert(&mut self, name: &str, pattern: &str) {
+        self.rules.insert(name.to_string(), Rule::from(pattern));
+    }
+
+    /// Names and patterns of the named rules
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.rules
+            .iter()
+            .map(|(name, rule)| (name.as_str(), rule.pattern.as_str()))
+    }
+
+    /// Names of the path parameters of the route named `name`
+    #[cfg(feature = "template")]
+    pub(crate) fn params(&self, name: &str) -> Option<Vec<&str>> {
+        self.rules.get(name).map(Rule::params)
+    }
+
+    /// Build the URL of the route named `name` with its path parameters and a query string
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, UrlError> {
+        let rule = self
+            .rules
+            .get(name)
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_string()))?;
+        let mut url = rule.build(params)?;
+        for (i, (key, value)) in query.iter().enumerate() {
+            url.push(if i == 0 { '?' } else { '&' });
+            url.extend(utf8_percent_encode(key, QUERY));
+            url.push('=');
+            url.extend(utf8_percent_encode(value, QUERY));
+        }
+        Ok(url)
+    }
+}
+
+/// Error returned when building the URL of a named route
+#[derive(Debug)]
+pub enum UrlError {
+    /// No route has this name
+    UnknownRoute(String),
+    /// A parameter of the route was not given
+    MissingParam { route: String, param: String },
+    /// A parameter value doesn't match its converter
+    InvalidParam {
+        route: String,
+        param: String,
+        value: String,
+    },
+}
+
+impl Display for UrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownRoute(name) => write!(f, "no route named {name}"),
+            Self::MissingParam { route, param } => {
+                write!(f, "missing parameter {param} for route {route}")
+            }
+            Self::InvalidParam {
+                route,
+                param,
+                value,
+            } => write!(f, "invalid value {value:?} of {param} for route {route}"),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+/// A route just added, which can be given a name to build its URL with `url_for`
+pub struct Route<'a> {
+    urls: &'a mut Urls,
+    pattern: String,
+}
+
+impl<'a> Route<'a> {
+    pub(crate) fn new(urls: &'a mut Urls, pattern: &str) -> Self {
+        Self {
+            urls,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    /// Name the route, the name must be unique across the application
+    /// # Example
+    /// ```
+    /// use haro::{Application, Response};
+    ///
+    /// let mut app = Application::new("0:8080");
+    /// app.get("/users/<id:int>", |_| Response::str("user")).name("user");
+    ///
+    /// let url = app.url_for("user", &[("id", "42")], &[("tab", "posts")]).unwrap();
+    /// assert_eq!("/users/42?tab=posts", url);
+    /// ```
+    pub fn name(self, name: &str) {
+        self.urls.insert(name, &self.pattern);
+    }
+}
+
+/// A rule with its handlers, stored at the node where its pattern ends
+#[derive(Clone)]
+struct Leaf {
+    rule: Rule,
+    endpoint: Endpoint,
+}
+
+/// Node of the prefix tree, matching `prefix` before its children
+#[derive(Default, Clone)]
+struct Node {
+    prefix: String,
+    children: Vec<Node>,
+    dynamics: Vec<Dynamic>,
+    route: Option<Leaf>,
+}
+
+/// Child of a node matching a parameter instead of static text
+#[derive(Clone)]
+struct Dynamic {
+    token: Token,
+    node: Node,
+}
+
+type Params = Vec<(String, String)>;
+
+impl Node {
+    /// Insert the remaining `tokens` of `rule` below this node, returns the route they lead to
+    fn insert(&mut self, tokens: &[Token], rule: Rule) -> &mut Leaf {
+        match tokens.split_first() {
+            None => self.route.get_or_insert_with(|| Leaf {
+                rule,
+                endpoint: Endpoint::default(),
+            }),
+            Some((Token::Static(text), rest)) => self.insert_static(text, rest, rule),
+            Some((token, rest)) => {
+                let i = match self.dynamics.iter().position(|d| d.token.same(token)) {
+                    Some(i) => i,
+                    None => {
+                        self.dynamics.push(Dynamic {
+                            token: token.clone(),
+                            node: Node::default(),
+                        });
+                        // stable sort keeps registration order between equal ranks
+                        self.dynamics.sort_by_key(|d| d.token.rank());
+                        self.dynamics
+                            .iter()
+                            .position(|d| d.token.same(token))
+                            .unwrap()
+                    }
+                };
+                self.dynamics[i].node.insert(rest, rule)
+            }
+        }
+    }
+
+    fn insert_static(&mut self, text: &str, tokens: &[Token], rule: Rule) -> &mut Leaf {
+        let Some(first) = text.chars().next() else {
+            return self.insert(tokens, rule);
+        };
+        let i = match self
+            .children
+            .iter()
+            .position(|c| c.prefix.starts_with(first))
+        {
+            Some(i) => i,
+            None => {
+                self.children.push(Node {
+                    prefix: text.to_string(),
+                    ..Node::default()
+                });
+                let child = self.children.last_mut().unwrap();
+                return child.insert(tokens, rule);
+            }
+        };
+        let child = &mut self.children[i];
+        let n = common_prefix(&child.prefix, text);
+        if n < child.prefix.len() {
+            child.split(n);
+        }
+        child.insert_static(&text[n..], tokens, rule)
+    }
+
+    /// Move everything after the first `n` bytes of the prefix into a new child
+    fn split(&mut self, n: usize) {
+        let tail = Node {
+            prefix: self.prefix.split_off(n),
+            children: std::mem::take(&mut self.children),
+            dynamics: std::mem::take(&mut self.dynamics),
+            route: self.route.take(),
+        };
+        self.children.push(tail);
+    }
+
+    /// Match `path` below this node, calling `visit` on every matching route until it
+    /// returns true
+    fn lookup<'a>(
+        &'a self,
+        path: &str,
+        params: &mut Params,
+        visit: &mut dyn FnMut(&'a Leaf, &Params) -> bool,
+    ) -> bool {
+        if path.is_empty() {
+            if let Some(route) = &self.route {
+                if visit(route, params) {
+                    return true;
+                }
+            }
+        }
+
+        if let Some(first) = path.chars().next() {
+            let child = self.children.iter().find(|c| c.prefix.starts_with(first));
+            if let Some(child) = child {
+                if let Some(rest) = path.strip_prefix(child.prefix.as_str()) {
+                    if child.lookup(rest, params, visit) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        let segment_end = path.find('/').unwrap_or(path.len());
+        let segment = &path[..segment_end];
+        // a wildcard taking the whole rest of the path is tried after every other wildcard match,
+        // so the routes going on after a wildcard win over the ones ending with it
+        let split = self
+            .dynamics
+            .partition_point(|d| !matches!(d.token, Token::CatchAll(_)));
+        let wholes = self.dynamics[..split]
+            .iter()
+            .filter(|d| matches!(d.token, Token::Wildcard(_)))
+            .map(|d| (d, true));
+        let attempts = self.dynamics[..split].iter().map(|d| (d, false));
+        let attempts = attempts
+            .chain(wholes)
+            .chain(self.dynamics[split..].iter().map(|d| (d, false)));
+        for (dynamic, whole) in attempts {
+            let n = params.len();
+            let matched = match &dynamic.token {
+                Token::Param(name, matcher) => {
+                    if segment.is_empty() || !matcher.matches(segment) {
+                        continue;
+                    }
+                    params.push((name.clone(), segment.to_string()));
+                    dynamic.node.lookup(&path[segment_end..], params, visit)
+                }
+                Token::Segment(regex, _) => {
+                    let Some(caps) = regex.captures(segment) else {
+                        continue;
+                    };
+                    for name in regex.capture_names().flatten() {
+                        if let Some(value) = caps.name(name) {
+                            params.push((name.to_string(), value.as_str().to_string()));
+                        }
+                    }
+                    dynamic.node.lookup(&path[segment_end..], params, visit)
+                }
+                Token::Wildcard(name) => {
+                    // the longest match wins, like a greedy regex
+                    let mut ends: Vec<usize> = match whole {
+                        true => vec![path.len()],
+                        false => path.match_indices('/').map(|(i, _)| i).collect(),
+                    };
+                    ends.retain(|&i| i > 0);
+                    ends.reverse();
+                    ends.into_iter().any(|end| {
+                        params.push((name.clone(), path[..end].to_string()));
+                        let found = dynamic.node.lookup(&path[end..], params, visit);
+                        params.pop();
+                        found
+                    })
+                }
+                Token::CatchAll(name) => {
+                    if !name.is_empty() {
+                        params.push((name.clone(), path.to_string()));
+                    }
+                    dynamic.node.lookup("", params, visit)
+                }
+                Token::Static(_) => unreachable!("static tokens are stored as children"),
+            };
+            if matched {
+                return true;
+            }
+            params.truncate(n);
+        }
+        false
+    }
+
+    fn handlers_mut(&mut self, f: &mut dyn FnMut(&mut DynHandler)) {
+        if let Some(route) = &mut self.route {
+            route.endpoint.handlers_mut(f);
+        }
+        for child in &mut self.children {
+            child.handlers_mut(f);
+        }
+        for dynamic in &mut self.dynamics {
+            dynamic.node.handlers_mut(f);
+        }
+    }
+
+    fn routes<'a>(&'a self, routes: &mut Vec<&'a Leaf>) {
+        routes.extend(self.route.iter());
+        for child in &self.children {
+            child.routes(routes);
+        }
+        for dynamic in &self.dynamics {
+            dynamic.node.routes(routes);
+        }
+    }
+}
+
+/// Length in bytes of the common prefix of `a` and `b`, on a char boundary
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
 #[derive(Debug, Clone)]
 struct Rule {
-    pattern: &'static str,
-    num_parts: usize,
-    regex: Option<Regex>,
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+/// Piece of a route pattern
+#[derive(Debug, Clone)]
+enum Token {
+    /// Text matched as is, including the slashes
+    Static(String),
+    /// A parameter taking a whole segment
+    Param(String, Matcher),
+    /// A segment mixing text and parameters such as `<name>.txt`, with its template
+    Segment(Regex, String),
+    /// A `path` parameter taking one or more segments
+    Wildcard(String),
+    /// A trailing `*name` taking the rest of the path, possibly empty, not captured if unnamed
+    CatchAll(String),
+}
+
+impl Token {
+    fn same(&self, other: &Token) -> bool {
+        match (self, other) {
+            (Self::Param(a, m), Self::Param(b, n)) => a == b && m.same(n),
+            (Self::Segment(a, _), Self::Segment(b, _)) => a.as_str() == b.as_str(),
+            (Self::Wildcard(a), Self::Wildcard(b)) | (Self::CatchAll(a), Self::CatchAll(b)) => {
+                a == b
+            }
+            _ => false,
+        }
+    }
+
+    /// Precedence between the dynamic children of a node, lower is tried first
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Static(_) => 0,
+            Self::Param(_, Matcher::Str) => 2,
+            Self::Param(..) | Self::Segment(..) => 1,
+            Self::Wildcard(_) => 3,
+            Self::CatchAll(_) => 4,
+        }
+    }
+}
+
+/// Converter checking the value of a parameter
+#[derive(Debug, Clone)]
+enum Matcher {
+    Str,
+    Int,
+    Uuid,
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn from(name: &str, pattern: &str) -> Self {
+        match name {
+            "str" => Self::Str,
+            "int" => Self::Int,
+            "uuid" => Self::Uuid,
+            regex => Self::Regex(compile(
+                pattern,
+                &format!("^(?:{})$", converter(pattern, regex)),
+            )),
+        }
+    }
+
+    fn matches(&self, segment: &str) -> bool {
+        match self {
+            Self::Str => true,
+            Self::Int => segment.bytes().all(|b| b.is_ascii_digit()),
+            Self::Uuid => {
+                segment.len() == 36
+                    && segment.bytes().enumerate().all(|(i, b)| match i {
+                        8 | 13 | 18 | 23 => b == b'-',
+                        _ => b.is_ascii_hexdigit(),
+                    })
+            }
+            Self::Regex(regex) => regex.is_match(segment),
+        }
+    }
+
+    fn same(&self, other: &Matcher) -> bool {
+        match (self, other) {
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl Rule {
+    /// Names of the parameters in the pattern
+    #[cfg(feature = "template")]
+    fn params(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for token in &self.tokens {
+            match token {
+                Token::Static(_) => {}
+                Token::Param(name, _) | Token::Wildcard(name) => names.push(name.as_str()),
+                Token::CatchAll(name) if !name.is_empty() => names.push(name.as_str()),
+                Token::CatchAll(_) => {}
+                Token::Segment(_, template) => {
+                    let mut rest = template.as_str();
+                    while let Some(start) = rest.find('<') {
+                        let end = start + rest[start..].find('>').unwrap();
+                        names.push(rest[start + 1..end].split(':').next().unwrap());
+                        rest = &rest[end + 1..];
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Build a path from the rule with the given parameters, percent-encoding their values
+    fn build(&self, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| UrlError::MissingParam {
+                    route: self.pattern.clone(),
+                    param: name.to_string(),
+                })
+        };
+        let invalid = |name: &str, value: &str| UrlError::InvalidParam {
+            route: self.pattern.clone(),
+            param: name.to_string(),
+            value: value.to_string(),
+        };
+
+        let mut path = String::new();
+        for token in &self.tokens {
+            match token {
+                Token::Static(text) => path.push_str(text),
+                Token::Param(name, matcher) => {
+                    let value = param(name)?;
+                    if value.is_empty() || !matcher.matches(value) {
+                        return Err(invalid(name, value));
+                    }
+                    path.extend(utf8_percent_encode(value, SEGMENT));
+                }
+                Token::Segment(regex, template) => {
+                    let mut rest = template.as_str();
+                    let mut segment = String::new();
+                    while let Some(start) = rest.find('<') {
+                        let end = start + rest[start..].find('>').unwrap();
+                        let name = rest[start + 1..end].split(':').next().unwrap();
+                        segment.push_str(&rest[..start]);
+                        segment.extend(utf8_percent_encode(param(name)?, SEGMENT));
+                        rest = &rest[end + 1..];
+                    }
+                    segment.push_str(rest);
+                    if !regex.is_match(&segment) {
+                        return Err(invalid(template, &segment));
+                    }
+                    path.push_str(&segment);
+                }
+                Token::Wildcard(name) => {
+                    let value = param(name)?;
+                    if value.is_empty() {
+                        return Err(invalid(name, value));
+                    }
+                    push_path(&mut path, value);
+                }
+                Token::CatchAll(name) if name.is_empty() => {}
+                Token::CatchAll(name) => push_path(&mut path, param(name)?),
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// Append a value spanning several segments, keeping its slashes
+fn push_path(path: &mut String, value: &str) {
+    let parts: Vec<String> = value
+        .split('/')
+        .map(|part| utf8_percent_encode(part, SEGMENT).to_string())
+        .collect();
+    path.push_str(&parts.join("/"));
 }
 
 impl Display for Rule {
@@ -83,56 +750,105 @@ impl Display for Rule {
     }
 }
 
-impl From<&'static str> for Rule {
-    fn from(pattern: &'static str) -> Self {
-        let mut parts = Vec::new();
-        let mut has_regex = false;
-        for part in pattern.split('/') {
-            if let Some(stripped) = part.strip_prefix(':') {
-                let regex_part = format!("(?P<{stripped}>.+)");
-                parts.push(regex_part);
-                has_regex = true
-            } else if !part.is_empty() {
-                parts.push(part.to_string())
+impl From<&str> for Rule {
+    /// Parse a route pattern, parameters are written as `:name` or `<name:converter>` where the
+    /// converter is `str` (the default), `int`, `uuid`, `path` or an inline regex, and a last
+    /// segment `*name` or `*` catches the rest of the path
+    fn from(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut text = String::new();
+        let parts = pattern.split('/').count();
+        for (i, part) in pattern.split('/').enumerate() {
+            if i > 0 {
+                text.push('/');
             }
+            if let Some(name) = part.strip_prefix('*') {
+                if i + 1 < parts {
+                    panic!("invalid route pattern {pattern}: `*` must be the last segment");
+                }
+                if !text.is_empty() {
+                    tokens.push(Token::Static(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::CatchAll(name.to_string()));
+                break;
+            }
+            let param = match part.strip_prefix(':') {
+                Some(name) => Some((name, "str")),
+                None => part
+                    .strip_prefix('<')
+                    .and_then(|p| p.strip_suffix('>'))
+                    .filter(|p| !p.contains('<'))
+                    .map(|p| p.split_once(':').unwrap_or((p, "str"))),
+            };
+            let token = match param {
+                Some((name, "path")) => Token::Wildcard(name.to_string()),
+                Some((name, converter)) => {
+                    Token::Param(name.to_string(), Matcher::from(converter, pattern))
+                }
+                None if part.contains('<') => {
+                    let regex = format!("^{}$", parse_part(pattern, part));
+                    Token::Segment(compile(pattern, &regex), part.to_string())
+                }
+                None => {
+                    text.push_str(part);
+                    continue;
+                }
+            };
+            if !text.is_empty() {
+                tokens.push(Token::Static(std::mem::take(&mut text)));
+            }
+            tokens.push(token);
+        }
+        if !text.is_empty() {
+            tokens.push(Token::Static(text));
         }
-        let regex = if has_regex {
-            let regex_str = format!("/{}", parts.join("/"));
-            Some(Regex::new(&regex_str).unwrap())
-        } else {
-            None
-        };
         Rule {
-            pattern,
-            num_parts: parts.len(),
-            regex,
+            pattern: pattern.to_string(),
+            tokens,
         }
     }
 }
 
-impl Rule {
-    fn _match(&self, path: &str) -> Option<HashMap<String, String>> {
-        if self.regex.is_none() {
-            if self.pattern == path {
-                return Some(HashMap::new());
-            }
-        } else {
-            let re = self.regex.as_ref().unwrap();
-            if let Some(caps) = re.captures(path) {
-                // CaptureNames: (Iter([None, Some("aaa")]))
-                // captures: Some(Captures({0: Some("aab"), "aaa": Some("aa")}))
-                return Some(
-                    re.capture_names()
-                        .flatten()
-                        .filter_map(|n| Some((n.to_string(), caps.name(n)?.as_str().to_string())))
-                        .collect(),
-                );
-            }
+fn compile(pattern: &str, regex: &str) -> Regex {
+    Regex::new(regex).unwrap_or_else(|e| panic!("invalid route pattern {pattern}: {e}"))
+}
+
+/// Regex matching a parameter of the given converter inside a segment, other converters are
+/// inline regexes
+///
+/// # Panics
+/// Panics if the converter is an unknown name, which is more likely a typo than a regex matching
+/// only that text
+fn converter<'a>(pattern: &str, name: &'a str) -> &'a str {
+    match name {
+        "str" | "path" => "[^/]+",
+        "int" => "[0-9]+",
+        "uuid" => "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+        name if name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') => {
+            panic!("invalid route pattern {pattern}: unknown converter `{name}`")
         }
-        None
+        regex => regex,
     }
 }
 
+/// Turn a segment such as `<name>.txt` into a regex
+fn parse_part(pattern: &str, mut part: &str) -> String {
+    let mut regex = String::new();
+    while let Some(start) = part.find('<') {
+        let end = part[start..]
+            .find('>')
+            .map(|end| start + end)
+            .unwrap_or_else(|| panic!("invalid route pattern {pattern}: unclosed `<`"));
+        regex.push_str(&regex::escape(&part[..start]));
+        let param = &part[start + 1..end];
+        let (name, conv) = param.split_once(':').unwrap_or((param, "str"));
+        regex.push_str(&format!("(?P<{name}>{})", converter(pattern, conv)));
+        part = &part[end + 1..];
+    }
+    regex.push_str(&regex::escape(part));
+    regex
+}
+
 fn not_found(_req: Request) -> Response {
     Response::new(
         StatusCode::NOT_FOUND,
@@ -140,3 +856,29 @@ fn not_found(_req: Request) -> Response {
         HashMap::new(),
     )
 }
+
+fn not_allowed(req: Request) -> Response {
+    let allow = req
+        .extensions()
+        .get::<Allow>()
+        .map(|allow| allow.0.as_str())
+        .unwrap_or_default();
+    match req.method() {
+        "OPTIONS" => options(allow),
+        _ => method_not_allowed(allow),
+    }
+}
+
+fn method_not_allowed(allow: &str) -> Response {
+    let headers = HashMap::from([(ALLOW, allow)]);
+    Response::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "405 Method Not Allowed".as_bytes(),
+        headers,
+    )
+}
+
+fn options(allow: &str) -> Response {
+    let headers = HashMap::from([(ALLOW, allow)]);
+    Response::new(StatusCode::NO_CONTENT, &[], headers)
+}
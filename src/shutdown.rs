@@ -0,0 +1,161 @@
+//! Graceful shutdown of a running `Application`
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// Handle to stop a running [`Application`](crate::Application)
+///
+/// Once shut down, the application stops accepting connections, lets the workers finish the
+/// requests in flight and runs its shutdown hooks before [`run`](crate::Application::run)
+/// returns. A `SIGTERM` or `SIGINT` shuts the application down the same way.
+/// # Example
+/// ```no_run
+/// use std::{thread, time::Duration};
+/// use haro::{Application, Response};
+///
+/// let mut app = Application::new("0:8080");
+/// app.route("/", |_| Response::str("Hello Haro"));
+///
+/// let handle = app.shutdown_handle();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_secs(60));
+///     handle.shutdown();
+/// });
+/// app.run();
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Shutdown>,
+}
+
+#[derive(Default)]
+struct Shutdown {
+    requested: AtomicBool,
+    /// Address the application listens on, to wake up the accept loop
+    addr: Mutex<Option<SocketAddr>>,
+}
+
+impl ShutdownHandle {
+    /// Ask the application to shut down, returns without waiting for it
+    pub fn shutdown(&self) {
+        if self.inner.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(addr) = self.addr() {
+            // the accept loop only checks for a shutdown when a connection comes in
+            let _ = TcpStream::connect(loopback(addr));
+        }
+    }
+
+    /// Whether a shutdown was requested
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Address the application listens on, once [`run`](crate::Application::run) has bound it,
+    /// such as the port picked by the system for an address ending with `:0`
+    pub fn addr(&self) -> Option<SocketAddr> {
+        *self.inner.addr.lock().unwrap()
+    }
+
+    /// Record the address the application listens on
+    pub(crate) fn bind(&self, addr: SocketAddr) {
+        *self.inner.addr.lock().unwrap() = Some(addr);
+    }
+}
+
+/// An `Application` running in a background thread, returned by
+/// [`spawn`](crate::Application::spawn)
+///
+/// Dropping the handle shuts the application down and waits for it to stop.
+pub struct ServerHandle {
+    addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    pub(crate) fn new(addr: SocketAddr, shutdown: ShutdownHandle, thread: JoinHandle<()>) -> Self {
+        Self {
+            addr,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// Address the application listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Full URL of `path` on the application
+    /// # Example
+    /// ```
+    /// use haro::Application;
+    ///
+    /// let server = Application::new("0:8080").spawn();
+    /// let url = server.url("/users");
+    /// assert_eq!(format!("http://{}/users", server.addr()), url);
+    /// ```
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// Shut the application down and wait for it to stop, same as dropping the handle
+    pub fn shutdown(self) {
+        drop(self);
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Address to connect to for a listener bound to `addr`
+fn loopback(mut addr: SocketAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        });
+    }
+    addr
+}
+
+/// Shut down on the first `SIGTERM` or `SIGINT`, exit right away on the next one
+#[cfg(unix)]
+pub(crate) fn watch_signals(handle: ShutdownHandle) -> Option<signal_hook::iterator::Handle> {
+    use log::{info, warn};
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = match Signals::new([SIGTERM, SIGINT]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("failed to register signal handlers: {e}");
+            return None;
+        }
+    };
+    let signals_handle = signals.handle();
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if handle.is_shutdown() {
+                warn!("received signal {signal} during shutdown, exiting");
+                std::process::exit(1);
+            }
+            info!("received signal {signal}, shutting down");
+            handle.shutdown();
+        }
+    });
+    Some(signals_handle)
+}
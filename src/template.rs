@@ -1,13 +1,85 @@
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
 use once_cell::sync::Lazy;
-use tera::Tera;
+use tera::{Function, Tera, Value};
+
+use crate::router::Urls;
 
 pub(crate) static TEMPLATES: Lazy<Tera> = Lazy::new(|| {
-    let tera = match Tera::new("templates/**/*") {
+    let mut tera = match Tera::new("templates/**/*") {
         Ok(t) => t,
         Err(e) => {
             println!("Parsing error(s): {e}");
             ::std::process::exit(1);
         }
     };
+    tera.register_function("url_for", UrlFor);
     tera
 });
+
+thread_local! {
+    /// Named routes of the application handling a request on this thread, used by the `url_for`
+    /// template function
+    static URLS: RefCell<Arc<Urls>> = RefCell::default();
+}
+
+/// Make `urls` the routes of `url_for` on this thread until the guard is dropped, so each
+/// application renders its templates against its own routes
+pub(crate) fn scope_urls(urls: Arc<Urls>) -> UrlsGuard {
+    UrlsGuard(URLS.with(|current| current.replace(urls)))
+}
+
+/// Restores the routes of the enclosing scope when dropped
+pub(crate) struct UrlsGuard(Arc<Urls>);
+
+impl Drop for UrlsGuard {
+    fn drop(&mut self) {
+        URLS.with(|current| *current.borrow_mut() = self.0.clone());
+    }
+}
+
+/// Template function building the URL of a named route, e.g.
+/// `{{ url_for(name="user", id=42, tab="posts") }}`. Arguments other than `name` fill the path
+/// parameters of the route, the remaining ones are added to the query string.
+struct UrlFor;
+
+impl Function for UrlFor {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        url_for(args)
+    }
+
+    /// Parameters are percent-encoded so the URL is safe to put in HTML
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+fn url_for(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let name = match args.get("name") {
+        Some(Value::String(name)) => name,
+        _ => return Err("url_for expects a `name` string argument".into()),
+    };
+    let urls = URLS.with(|urls| urls.borrow().clone());
+    let route_params = urls.params(name).unwrap_or_default();
+
+    let mut values: Vec<(&str, String)> = args
+        .iter()
+        .filter(|(key, _)| *key != "name")
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            (key.as_str(), value)
+        })
+        .collect();
+    values.sort();
+    let (params, query): (Vec<_>, Vec<_>) = values
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .partition(|(key, _)| route_params.contains(key));
+
+    urls.url_for(name, &params, &query)
+        .map(Value::String)
+        .map_err(|e| e.to_string().into())
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use haro::{Application, Blueprint, DynHandler, Request, Response};
+use http::StatusCode;
+
+mod common;
+
+use common::request;
+
+/// Middleware appending `name` to the `x-trace` header, to check the wrapping order
+fn trace(name: &'static str) -> impl Fn(DynHandler) -> DynHandler {
+    move |next: DynHandler| -> DynHandler {
+        Arc::new(move |req: Request| -> Response { next(req).header("x-trace", name) })
+    }
+}
+
+fn traces(res: &Response) -> Vec<&str> {
+    res.headers()
+        .get_all("x-trace")
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_blueprint() {
+    let mut users = Blueprint::new("/users");
+    users.middleware(trace("users"));
+    users.get("/", |_| Response::str("list users"));
+    users.get("/<id:int>", |req| Response::str(&req.params["id"]));
+    users.post("/<id:int>", |_| Response::str("update user"));
+
+    let mut api = Blueprint::new("/api/v1/");
+    api.middleware(trace("api"));
+    api.route("/health", |_| Response::str("ok"));
+    api.mount(users);
+
+    let mut app = Application::new("0:8080");
+    app.middleware(trace("app"));
+    app.route("/", |_| Response::str("index"));
+    app.mount(api);
+
+    let res = request(&app, "GET", "/api/v1/users/42");
+    assert_eq!(b"42", res.body());
+    // handlers are wrapped from the innermost blueprint outwards
+    assert_eq!(vec!["users", "api", "app"], traces(&res));
+
+    let res = request(&app, "POST", "/api/v1/users/42");
+    assert_eq!(b"update user", res.body());
+
+    let res = request(&app, "GET", "/api/v1/users/");
+    assert_eq!(b"list users", res.body());
+
+    let res = request(&app, "GET", "/api/v1/health");
+    assert_eq!(b"ok", res.body());
+    assert_eq!(vec!["api", "app"], traces(&res));
+
+    let res = request(&app, "GET", "/");
+    assert_eq!(vec!["app"], traces(&res));
+
+    let res = request(&app, "GET", "/users/42");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+}
+
+#[test]
+fn test_blueprint_url_for() {
+    let mut users = Blueprint::new("/users");
+    users
+        .get("/<id:int>", |_| Response::str("user"))
+        .name("user");
+
+    let mut api = Blueprint::new("/api/v1/");
+    api.get("/status", |_| Response::str("ok")).name("status");
+    api.mount(users);
+
+    let mut app = Application::new("0:8080");
+    app.mount(api);
+
+    assert_eq!("/api/v1/status", app.url_for("status", &[], &[]).unwrap());
+    assert_eq!(
+        "/api/v1/users/1",
+        app.url_for("user", &[("id", "1")], &[]).unwrap()
+    );
+}
+
+#[test]
+fn test_blueprint_not_found() {
+    let mut users = Blueprint::new("/users");
+    users.middleware(trace("users"));
+    users.get("/<id:int>", |_| Response::str("user"));
+    users.not_found(|_| Response::str("no user").with_status(StatusCode::NOT_FOUND));
+
+    let mut api = Blueprint::new("/api");
+    api.middleware(trace("api"));
+    api.not_found(|_| Response::str("no api").with_status(StatusCode::NOT_FOUND));
+    api.mount(users);
+
+    let mut app = Application::new("0:8080");
+    app.not_found(|_| Response::str("no page").with_status(StatusCode::NOT_FOUND));
+    app.mount(api);
+
+    let res = request(&app, "GET", "/api/users/abc");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+    assert_eq!(b"no user", res.body());
+    assert_eq!(vec!["users", "api"], traces(&res));
+
+    let res = request(&app, "GET", "/api/users");
+    assert_eq!(b"no user", res.body());
+
+    let res = request(&app, "GET", "/api/usersx");
+    assert_eq!(b"no api", res.body());
+    assert_eq!(vec!["api"], traces(&res));
+
+    assert_eq!(b"no page", request(&app, "GET", "/apix").body());
+    assert_eq!(b"user", request(&app, "GET", "/api/users/1").body());
+}
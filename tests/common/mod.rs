@@ -0,0 +1,25 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use haro::{Application, Response};
+
+/// Response of `app` to a request without headers or body
+pub fn request(app: &Application, method: &str, uri: &str) -> Response {
+    request_with(app, method, uri, &[])
+}
+
+/// Response of `app` to a request with `headers` and no body
+pub fn request_with(
+    app: &Application,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> Response {
+    let headers = headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<HashMap<_, _>>();
+    app.request(method, uri, headers, &[])
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use haro::{middleware, Application, Response};
+use http::{Method, StatusCode};
+
+mod common;
+
+use common::request_with;
+
+fn header<'a>(res: &'a Response, name: &str) -> Option<&'a str> {
+    res.headers().get(name).map(|v| v.to_str().unwrap())
+}
+
+fn app() -> Application {
+    let mut app = Application::new("0:8080");
+    app.cors(
+        middleware::cors()
+            .allow_origin("https://app.example.com")
+            .allow_origin_regex(r"^https://[a-z]+\.haro\.dev$")
+            .allow_methods(&[Method::GET, Method::POST])
+            .allow_headers(&["Content-Type", "authorization"])
+            .expose_headers(&["x-request-id"])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600)),
+    );
+    app.get("/users", |_| {
+        Response::str("users").header("x-request-id", "1")
+    });
+    app
+}
+
+#[test]
+fn test_cors_preflight() {
+    let app = app();
+
+    let res = request_with(
+        &app,
+        "OPTIONS",
+        "/users",
+        &[
+            ("origin", "https://app.example.com"),
+            ("access-control-request-method", "POST"),
+            (
+                "access-control-request-headers",
+                "content-type, Authorization",
+            ),
+        ],
+    );
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+    assert!(res.body().is_empty());
+    assert_eq!(
+        Some("https://app.example.com"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(
+        Some("GET, POST"),
+        header(&res, "access-control-allow-methods")
+    );
+    assert_eq!(
+        Some("content-type, authorization"),
+        header(&res, "access-control-allow-headers")
+    );
+    assert_eq!(
+        Some("true"),
+        header(&res, "access-control-allow-credentials")
+    );
+    assert_eq!(Some("600"), header(&res, "access-control-max-age"));
+    assert!(res.headers().get_all("vary").iter().any(|v| v == "Origin"));
+
+    // preflights are answered for paths without routes too
+    let res = request_with(
+        &app,
+        "OPTIONS",
+        "/missing",
+        &[
+            ("origin", "https://api.haro.dev"),
+            ("access-control-request-method", "GET"),
+        ],
+    );
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+    assert_eq!(
+        Some("https://api.haro.dev"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(
+        Some("content-type, authorization"),
+        header(&res, "access-control-allow-headers")
+    );
+
+    // disallowed origins, methods and headers fall through to the router
+    for (origin, method, headers) in [
+        ("https://evil.com", "GET", ""),
+        ("https://app.example.com", "DELETE", ""),
+        ("https://app.example.com", "GET", "x-secret"),
+    ] {
+        let headers = [
+            ("origin", origin),
+            ("access-control-request-method", method),
+            ("access-control-request-headers", headers),
+        ];
+        let res = request_with(&app, "OPTIONS", "/users", &headers);
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        assert_eq!(Some("GET, HEAD, OPTIONS"), header(&res, "allow"));
+        assert_eq!(None, header(&res, "access-control-allow-origin"));
+    }
+}
+
+#[test]
+fn test_cors_actual_request() {
+    let app = app();
+
+    let res = request_with(&app, "GET", "/users", &[("origin", "https://www.haro.dev")]);
+    assert_eq!(b"users", res.body());
+    assert_eq!(
+        Some("https://www.haro.dev"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(
+        Some("true"),
+        header(&res, "access-control-allow-credentials")
+    );
+    assert_eq!(
+        Some("x-request-id"),
+        header(&res, "access-control-expose-headers")
+    );
+    assert_eq!(Some("Origin"), header(&res, "vary"));
+    assert_eq!(None, header(&res, "access-control-allow-methods"));
+
+    // same origin and unknown origins get no CORS headers, but caches must still tell origins
+    // apart
+    for headers in [&[][..], &[("origin", "https://www.haro.dev.evil.com")][..]] {
+        let res = request_with(&app, "GET", "/users", headers);
+        assert_eq!(b"users", res.body());
+        assert_eq!(None, header(&res, "access-control-allow-origin"));
+        assert_eq!(Some("Origin"), header(&res, "vary"));
+    }
+}
+
+#[test]
+fn test_cors_hooks() {
+    let mut app = app();
+    app.before_request(|req| match req.headers().contains_key("authorization") {
+        true => None,
+        false => Some(Response::str("login first").with_status(StatusCode::UNAUTHORIZED)),
+    });
+    app.error_handler(StatusCode::NOT_FOUND, |_| {
+        (StatusCode::NOT_FOUND, "nothing here")
+    });
+    app.after_request(|res| res.header("x-frame-options", "DENY"));
+    let origin = ("origin", "https://app.example.com");
+
+    // preflights don't carry credentials, they are answered before the hooks
+    let res = request_with(
+        &app,
+        "OPTIONS",
+        "/users",
+        &[origin, ("access-control-request-method", "GET")],
+    );
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+    assert_eq!(
+        Some("https://app.example.com"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(Some("DENY"), header(&res, "x-frame-options"));
+
+    // responses of the hooks and error handlers are decorated too
+    let res = request_with(&app, "GET", "/users", &[origin]);
+    assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+    assert_eq!(
+        Some("https://app.example.com"),
+        header(&res, "access-control-allow-origin")
+    );
+    let res = request_with(&app, "GET", "/missing", &[origin, ("authorization", "x")]);
+    assert_eq!(b"nothing here", res.body());
+    assert_eq!(
+        Some("https://app.example.com"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(
+        Some("x-request-id"),
+        header(&res, "access-control-expose-headers")
+    );
+}
+
+#[test]
+fn test_cors_any_origin() {
+    let mut app = Application::new("0:8080");
+    app.cors(middleware::cors().allow_origin("*").allow_headers(&["*"]));
+    app.post("/", |_| Response::str("created"));
+
+    let res = request_with(
+        &app,
+        "OPTIONS",
+        "/",
+        &[
+            ("origin", "https://any.com"),
+            ("access-control-request-method", "POST"),
+            ("access-control-request-headers", "x-a, x-b"),
+        ],
+    );
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+    assert_eq!(Some("*"), header(&res, "access-control-allow-origin"));
+    assert_eq!(
+        Some("x-a, x-b"),
+        header(&res, "access-control-allow-headers")
+    );
+    assert_eq!(None, header(&res, "access-control-allow-credentials"));
+    assert_eq!(None, header(&res, "access-control-max-age"));
+
+    let res = request_with(&app, "POST", "/", &[("origin", "https://any.com")]);
+    assert_eq!(b"created", res.body());
+    assert_eq!(Some("*"), header(&res, "access-control-allow-origin"));
+    assert_eq!(None, header(&res, "vary"));
+
+    // credentials can't be shared with every origin, so the origin is echoed
+    let mut app = Application::new("0:8080");
+    app.cors(middleware::cors().allow_origin("*").allow_credentials(true));
+    app.get("/", |_| Response::str("hello"));
+    let res = request_with(&app, "GET", "/", &[("origin", "https://any.com")]);
+    assert_eq!(
+        Some("https://any.com"),
+        header(&res, "access-control-allow-origin")
+    );
+    assert_eq!(Some("Origin"), header(&res, "vary"));
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use haro::{Application, DynHandler, Request, Response};
+
+#[derive(Debug, Clone, PartialEq)]
+struct RequestId(u64);
+
+struct User {
+    name: String,
+}
+
+/// Attach the user to the request and copy the cache status of the response into a header
+fn auth(next: DynHandler) -> DynHandler {
+    Arc::new(move |mut req: Request| -> Response {
+        if let Some(name) = req.headers().get("x-user") {
+            let name = name.to_str().unwrap().to_string();
+            req.extensions_mut().insert(User { name });
+        }
+        req.extensions_mut().insert(RequestId(42));
+        let res = next(req);
+        let cache = match res.extensions().get::<Cache>() {
+            Some(Cache::Hit) => "hit",
+            Some(Cache::Miss) => "miss",
+            None => "none",
+        };
+        res.header("x-cache", cache)
+    })
+}
+
+enum Cache {
+    Hit,
+    Miss,
+}
+
+fn hello(req: Request) -> Response {
+    let id = req.extensions().get::<RequestId>().unwrap();
+    match req.extensions().get::<User>() {
+        Some(user) => Response::str(format!("{} {}", id.0, user.name)).extension(Cache::Hit),
+        None => Response::str(format!("{} anonymous", id.0))
+            .header("x-anonymous", "1")
+            .extension(Cache::Miss),
+    }
+}
+
+#[test]
+fn test_extensions() {
+    let mut app = Application::new("0:8080");
+    app.middleware(auth);
+    app.route("/", hello);
+    app.route("/plain", |_| Response::str("plain"));
+
+    let headers = HashMap::from([("x-user".to_string(), "Haro".to_string())]);
+    let res = app.request("GET", "/", headers, &[]);
+    assert_eq!(b"42 Haro", res.body());
+    assert_eq!("hit", res.headers()["x-cache"]);
+
+    let res = app.request("GET", "/", HashMap::new(), &[]);
+    assert_eq!(b"42 anonymous", res.body());
+    assert_eq!("miss", res.headers()["x-cache"]);
+
+    let res = app.request("GET", "/plain", HashMap::new(), &[]);
+    assert_eq!("none", res.headers()["x-cache"]);
+
+    // values are kept when the response is rebuilt
+    let mut res = Response::str("Hello Haro")
+        .extension(RequestId(1))
+        .header("x-a", "1")
+        .chunked();
+    assert_eq!(Some(&RequestId(1)), res.extensions().get());
+    res.extensions_mut().insert(RequestId(2));
+    assert_eq!(Some(RequestId(2)), res.extensions_mut().remove());
+    assert!(res.extensions().get::<RequestId>().is_none());
+}
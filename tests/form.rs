@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use haro::{Application, Request, Response};
+use http::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+
+fn post(app: &Application, content_type: &str, body: &[u8]) -> Response {
+    post_to(app, "/", content_type, body)
+}
+
+fn post_to(app: &Application, uri: &str, content_type: &str, body: &[u8]) -> Response {
+    let headers = HashMap::from([("content-type".to_string(), content_type.to_string())]);
+    app.request("POST", uri, headers, body)
+}
+
+fn echo(req: Request) -> Response {
+    let files: HashMap<_, Vec<_>> = req
+        .files()
+        .iter()
+        .map(|(name, files)| {
+            let files = files
+                .iter()
+                .map(|f| {
+                    json!({
+                        "filename": f.filename(),
+                        "content_type": f.content_type(),
+                        "size": f.size(),
+                        "content": String::from_utf8(f.bytes().unwrap()).unwrap(),
+                    })
+                })
+                .collect();
+            (name.clone(), files)
+        })
+        .collect();
+    Response::json(json!({"data": req.data, "files": files}))
+}
+
+fn json_body(res: &Response) -> serde_json::Value {
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+#[test]
+fn test_urlencoded() {
+    let mut app = Application::new("0:8080");
+    app.post("/", echo);
+
+    let res = post(
+        &app,
+        "application/x-www-form-urlencoded",
+        b"name=Haro+Ray&city=Z%C3%BCrich&empty=&flag",
+    );
+    assert_eq!(
+        json!({"name": "Haro Ray", "city": "Zürich", "empty": "", "flag": ""}),
+        json_body(&res)["data"]
+    );
+
+    let res = post(
+        &app,
+        "application/json; charset=utf-8",
+        br#"{"name":"Haro"}"#,
+    );
+    assert_eq!(json!({"name": "Haro"}), json_body(&res)["data"]);
+}
+
+#[test]
+fn test_multipart() {
+    let mut app = Application::new("0:8080");
+    app.post("/", echo);
+
+    let body = concat!(
+        "preamble\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"title\"\r\n",
+        "\r\n",
+        "Hello\r\nHaro\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"docs\"; filename=\"a.txt\"\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "first --XyZ file\r\n",
+        "--XyZ\r\n",
+        "content-disposition: form-data; name=\"docs\"; filename=\"b.txt\"\r\n",
+        "\r\n",
+        "\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"empty\"; filename=\"\"\r\n",
+        "Content-Type: application/octet-stream\r\n",
+        "\r\n",
+        "\r\n",
+        "--XyZ--\r\n",
+        "epilogue",
+    );
+    let res = post(
+        &app,
+        "multipart/form-data; boundary=\"XyZ\"",
+        body.as_bytes(),
+    );
+    assert_eq!(
+        json!({
+            "data": {"title": "Hello\r\nHaro"},
+            "files": {"docs": [
+                {"filename": "a.txt", "content_type": "text/plain", "size": 16, "content": "first --XyZ file"},
+                {"filename": "b.txt", "content_type": null, "size": 0, "content": ""},
+            ]},
+        }),
+        json_body(&res)
+    );
+
+    // a truncated body is ignored
+    let res = post(
+        &app,
+        "multipart/form-data; boundary=XyZ",
+        &body.as_bytes()[..80],
+    );
+    assert_eq!(json!({"data": {}, "files": {}}), json_body(&res));
+}
+
+#[test]
+fn test_query() {
+    let mut app = Application::new("0:8080");
+    app.get("/", |req| {
+        assert_eq!(vec!["a", "b c"], req.args.get_all("tag"));
+        assert_eq!(Some("a"), req.args.get("tag"));
+        assert_eq!("x=y&z", &req.args["expr"]);
+        assert_eq!("", &req.args["flag"]);
+        assert_eq!("Zürich", &req.args["city"]);
+        assert!(req.args.get_all("missing").is_empty());
+        Response::json(json!({ "args": req.args }))
+    });
+
+    let uri = "/?tag=a&tag=b+c&expr=x%3Dy%26z&flag&city=Z%C3%BCrich&&";
+    let res = app.request("GET", uri, HashMap::new(), &[]);
+    assert_eq!(
+        json!({"tag": ["a", "b c"], "expr": "x=y&z", "flag": "", "city": "Zürich"}),
+        json_body(&res)["args"]
+    );
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Search {
+    q: String,
+    page: u32,
+    exact: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Profile {
+    name: String,
+    age: u8,
+    address: Address,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Address {
+    city: String,
+}
+
+#[test]
+fn test_extract() {
+    let mut app = Application::new("0:8080");
+    app.get("/search", |req| match req.query::<Search>() {
+        Ok(search) => Response::str(format!("{search:?}")),
+        Err(e) => e.into(),
+    });
+    app.post("/profile", |req| match req.json::<Profile>() {
+        Ok(profile) => Response::str(format!("{profile:?}")),
+        Err(e) => e.into(),
+    });
+    app.post("/form", |req| match req.form::<Search>() {
+        Ok(search) => Response::str(format!("{search:?}")),
+        Err(e) => e.into(),
+    });
+    app.get("/users/<id:int>/<name>", |req| {
+        match req.params::<HashMap<String, String>>() {
+            Ok(params) => Response::str(format!("{} {}", params["id"], params["name"])),
+            Err(e) => e.into(),
+        }
+    });
+
+    let res = app.request("GET", "/search?q=haro+ray&page=2", HashMap::new(), &[]);
+    assert_eq!(
+        br#"Search { q: "haro ray", page: 2, exact: None }"#,
+        res.body()
+    );
+    let res = app.request("GET", "/search?q=haro&page=-1", HashMap::new(), &[]);
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    assert!(String::from_utf8_lossy(res.body()).starts_with("invalid query string: page: "));
+    let res = app.request("GET", "/search", HashMap::new(), &[]);
+    assert_eq!(b"invalid query string: missing field `q`", res.body());
+
+    let body = br#"{"name": "Haro", "age": 3, "address": {"city": "Tokyo"}}"#;
+    let res = post_to(&app, "/profile", "application/json", body);
+    assert_eq!(
+        br#"Profile { name: "Haro", age: 3, address: Address { city: "Tokyo" } }"#,
+        res.body()
+    );
+    let body = br#"{"name": "Haro", "age": 3, "address": {"city": 1}}"#;
+    let res = post_to(&app, "/profile", "application/json", body);
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    assert!(String::from_utf8_lossy(res.body()).starts_with("invalid json body: address.city: "));
+
+    let res = post_to(
+        &app,
+        "/form",
+        "application/x-www-form-urlencoded",
+        b"q=a&page=1&exact=true",
+    );
+    assert_eq!(
+        br#"Search { q: "a", page: 1, exact: Some(true) }"#,
+        res.body()
+    );
+    let body = "--b\r\nContent-Disposition: form-data; name=\"q\"\r\n\r\na\r\n\
+                --b\r\nContent-Disposition: form-data; name=\"page\"\r\n\r\n3\r\n--b--\r\n";
+    let res = post_to(
+        &app,
+        "/form",
+        "multipart/form-data; boundary=b",
+        body.as_bytes(),
+    );
+    assert_eq!(br#"Search { q: "a", page: 3, exact: None }"#, res.body());
+    let res = post_to(&app, "/form", "application/json", br#"{"q": "a"}"#);
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+
+    let res = app.request("GET", "/users/7/haro%20ray", HashMap::new(), &[]);
+    assert_eq!(b"7 haro ray", res.body());
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Filter {
+    tag: Vec<String>,
+    #[serde(default)]
+    id: Vec<u32>,
+    page: Option<u32>,
+}
+
+#[test]
+fn test_extract_sequences() {
+    let mut app = Application::new("0:8080");
+    app.get("/", |req| match req.query::<Filter>() {
+        Ok(filter) => Response::str(format!("{filter:?}")),
+        Err(e) => e.into(),
+    });
+    app.post("/", |req| match req.form::<Filter>() {
+        Ok(filter) => Response::str(format!("{filter:?}")),
+        Err(e) => e.into(),
+    });
+
+    let res = app.request("GET", "/?tag=a&id=1&tag=b&id=2&page=3", HashMap::new(), &[]);
+    assert_eq!(
+        br#"Filter { tag: ["a", "b"], id: [1, 2], page: Some(3) }"#,
+        res.body()
+    );
+    // a single value fills a sequence too
+    let res = app.request("GET", "/?tag=a", HashMap::new(), &[]);
+    assert_eq!(br#"Filter { tag: ["a"], id: [], page: None }"#, res.body());
+    let res = app.request("GET", "/?tag=a&id=1&id=x", HashMap::new(), &[]);
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    assert!(String::from_utf8_lossy(res.body()).starts_with("invalid query string: id[1]: "));
+
+    let res = post_to(
+        &app,
+        "/",
+        "application/x-www-form-urlencoded",
+        b"tag=a&tag=b+c",
+    );
+    assert_eq!(
+        br#"Filter { tag: ["a", "b c"], id: [], page: None }"#,
+        res.body()
+    );
+}
+
+#[test]
+fn test_json_data() {
+    let mut app = Application::new("0:8080");
+    app.post("/", echo);
+
+    let body = br#"{"name": "Haro", "age": 3, "tags": ["a"], "admin": false}"#;
+    let res = post(&app, "application/json", body);
+    assert_eq!(
+        json!({"name": "Haro", "age": "3", "tags": "[\"a\"]", "admin": "false"}),
+        json_body(&res)["data"]
+    );
+}
@@ -0,0 +1,175 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+use haro::{Application, DynHandler, Request, Response};
+use http::StatusCode;
+use serde_json::json;
+
+mod common;
+
+use common::request;
+
+/// Record the order in which hooks, middlewares and handlers run
+fn trace(events: &Arc<Mutex<Vec<String>>>, event: impl Into<String>) {
+    events.lock().unwrap().push(event.into());
+}
+
+#[test]
+fn test_request_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut app = Application::new("0:8080");
+
+    let e = events.clone();
+    app.before_request(move |req| {
+        trace(&e, "before");
+        req.params.insert("hooked".to_string(), "yes".to_string());
+        None
+    });
+    app.before_request(|req| match req.path() {
+        "/private" => Some(Response::str("denied").with_status(StatusCode::FORBIDDEN)),
+        _ => None,
+    });
+    let e = events.clone();
+    app.middleware(move |next: DynHandler| -> DynHandler {
+        let e = e.clone();
+        Arc::new(move |req: Request| {
+            trace(&e, "middleware");
+            next(req)
+        })
+    });
+    let e = events.clone();
+    app.after_request(move |res| {
+        trace(&e, format!("after {}", res.status().as_u16()));
+        res.header("x-after", "1")
+    });
+    let e = events.clone();
+    app.teardown_request(move |panic| trace(&e, format!("teardown {panic:?}")));
+    let e = events.clone();
+    app.get("/", move |req| {
+        trace(&e, "handler");
+        Response::str(&req.params["hooked"])
+    });
+    app.get("/private", |_| Response::str("secret"));
+    app.get("/panic", |_| -> Response { panic!("boom") });
+
+    let res = request(&app, "GET", "/");
+    assert_eq!(b"yes", res.body());
+    assert_eq!("1", res.headers()["x-after"]);
+    assert_eq!(
+        vec![
+            "before",
+            "middleware",
+            "handler",
+            "after 200",
+            "teardown None"
+        ],
+        std::mem::take(&mut *events.lock().unwrap())
+    );
+
+    // a before hook answers the request
+    let res = request(&app, "GET", "/private");
+    assert_eq!(StatusCode::FORBIDDEN, res.status());
+    assert_eq!(b"denied", res.body());
+    assert_eq!("1", res.headers()["x-after"]);
+    assert_eq!(
+        vec!["before", "after 403", "teardown None"],
+        std::mem::take(&mut *events.lock().unwrap())
+    );
+
+    // after and teardown hooks run after a panic
+    let res = request(&app, "GET", "/panic");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!("1", res.headers()["x-after"]);
+    assert_eq!(
+        vec![
+            "before",
+            "middleware",
+            "after 500",
+            "teardown Some(\"boom\")"
+        ],
+        std::mem::take(&mut *events.lock().unwrap())
+    );
+}
+
+#[test]
+fn test_after_hook_panic() {
+    let mut app = Application::new("0:8080");
+    app.after_request(|res| match res.status() {
+        StatusCode::OK => panic!("after boom"),
+        _ => res,
+    });
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = Mutex::new(tx);
+    app.teardown_request(move |panic| {
+        let panic = panic.map(str::to_string);
+        tx.lock().unwrap().send(panic).unwrap();
+    });
+    app.get("/", |_| Response::str("ok"));
+
+    let res = request(&app, "GET", "/");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!(Some("after boom".to_string()), rx.try_recv().unwrap());
+}
+
+#[test]
+fn test_error_handler() {
+    let mut app = Application::new("127.0.0.1:0").max_body_size(4);
+    app.error_handler(StatusCode::NOT_FOUND, |res: Response| {
+        let body = json!({"error": String::from_utf8_lossy(res.body())});
+        (StatusCode::NOT_FOUND, Response::json(body))
+    });
+    app.error_handler(StatusCode::METHOD_NOT_ALLOWED, |res: Response| {
+        let allow = res.headers()["allow"].to_str().unwrap().to_string();
+        (StatusCode::METHOD_NOT_ALLOWED, format!("use {allow}"))
+    });
+    app.error_handler(StatusCode::INTERNAL_SERVER_ERROR, |_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "something broke")
+    });
+    app.error_handler(StatusCode::PAYLOAD_TOO_LARGE, |_| {
+        (StatusCode::PAYLOAD_TOO_LARGE, "too big")
+    });
+    app.get("/users", |_| Response::str("users"));
+    app.get("/users/<id:int>", |req| {
+        match req.param::<u32>("id").unwrap() {
+            1 => Response::str("Haro"),
+            _ => Response::str("no such user").with_status(StatusCode::NOT_FOUND),
+        }
+    });
+    app.get("/panic", |_| -> Response { panic!("boom") });
+    app.after_request(|res| res.header("x-after", "1"));
+
+    let res = request(&app, "GET", "/missing");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+    assert_eq!(br#"{"error":"404 Not Found"}"#, res.body());
+    // error handlers run before the after hooks
+    assert_eq!("1", res.headers()["x-after"]);
+
+    // responses from handlers are rewritten too
+    let res = request(&app, "GET", "/users/2");
+    assert_eq!(br#"{"error":"no such user"}"#, res.body());
+    assert_eq!(b"Haro", request(&app, "GET", "/users/1").body());
+
+    let res = request(&app, "DELETE", "/users");
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+    assert_eq!(b"use GET, HEAD, OPTIONS", res.body());
+
+    let res = request(&app, "GET", "/panic");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!(b"something broke", res.body());
+    assert_eq!("1", res.headers()["x-after"]);
+
+    // and so are the responses to malformed requests
+    let server = app.spawn();
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    write!(stream, "POST /users HTTP/1.1\r\nContent-Length: 5\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert_eq!("HTTP/1.1 413 Payload Too Large\r\n", status);
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert!(rest.contains("connection: close\r\n"));
+    assert!(rest.contains("x-after: 1\r\n"));
+    assert!(rest.ends_with("\r\n\r\ntoo big"));
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::fs;
+
+use haro::{Application, Error, Request, Response};
+use http::{header::CONTENT_TYPE, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+mod common;
+
+use common::request;
+
+fn content_type(res: &Response) -> &str {
+    res.headers()[CONTENT_TYPE].to_str().unwrap()
+}
+
+#[derive(Deserialize)]
+struct Page {
+    page: u32,
+}
+
+fn user(req: Request) -> Result<Value, Error> {
+    let id: u32 = req.param("id")?;
+    let page: Page = req.query()?;
+    match id {
+        1 => Ok(json!({"id": id, "page": page.page})),
+        _ => Err(Error::json(
+            StatusCode::NOT_FOUND,
+            json!({"error": "no such user"}),
+        )),
+    }
+}
+
+#[test]
+fn test_into_response() {
+    let mut app = Application::new("0:8080");
+    app.get("/str", |_| "Hello Haro");
+    app.get("/string", |req| format!("Hello {}", req.path()));
+    app.get("/json", |_| json!({"name": "Haro"}));
+    app.post("/created", |_| (StatusCode::CREATED, "created"));
+    app.get("/users/<id>", user);
+
+    let res = request(&app, "GET", "/str");
+    assert_eq!(b"Hello Haro", res.body());
+    assert_eq!("text/plain", content_type(&res));
+    assert_eq!(b"Hello /string", request(&app, "GET", "/string").body());
+
+    let res = request(&app, "GET", "/json");
+    assert_eq!(br#"{"name":"Haro"}"#, res.body());
+    assert_eq!("application/json", content_type(&res));
+
+    let res = app.request("POST", "/created", HashMap::new(), &[]);
+    assert_eq!(StatusCode::CREATED, res.status());
+    assert_eq!(b"created", res.body());
+
+    let res = request(&app, "GET", "/users/1?page=2");
+    assert_eq!(StatusCode::OK, res.status());
+    assert_eq!(br#"{"id":1,"page":2}"#, res.body());
+
+    let res = request(&app, "GET", "/users/2?page=1");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+    assert_eq!(br#"{"error":"no such user"}"#, res.body());
+
+    // framework errors keep their status
+    let res = request(&app, "GET", "/users/abc?page=1");
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    assert_eq!(b"invalid value \"abc\" for path parameter id", res.body());
+    let res = request(&app, "GET", "/users/1");
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+}
+
+#[test]
+fn test_error() {
+    let mut app = Application::new("0:8080");
+    app.get("/io", |_| -> Result<String, Error> {
+        Ok(fs::read_to_string("/no/such/file")?)
+    });
+    app.get("/gone", |_| {
+        Err::<Response, _>(Error::new(StatusCode::GONE))
+    });
+    app.get("/html", |_| {
+        Err::<Response, _>(Error::html(StatusCode::FORBIDDEN, "<h1>Forbidden</h1>"))
+    });
+
+    // details of other errors are not sent to the client
+    let res = request(&app, "GET", "/io");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!(b"500 Internal Server Error", res.body());
+
+    let res = request(&app, "GET", "/gone");
+    assert_eq!(StatusCode::GONE, res.status());
+    assert_eq!(b"410 Gone", res.body());
+
+    let res = request(&app, "GET", "/html");
+    assert_eq!(StatusCode::FORBIDDEN, res.status());
+    assert_eq!(b"<h1>Forbidden</h1>", res.body());
+    assert_eq!("text/html; charset=utf-8", content_type(&res));
+
+    let e = Error::from(std::io::Error::other("disk full"));
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, e.status());
+    assert_eq!("500 Internal Server Error: disk full", e.to_string());
+    assert!(e.source().is_some());
+}
+
+#[test]
+fn test_panic_handler() {
+    let mut app = Application::new("0:8080");
+    app.get("/", |_| -> Response { panic!("boom {}", 42) });
+
+    let res = request(&app, "GET", "/");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!(b"500 Internal Server Error", res.body());
+
+    app.panic_handler(|message| {
+        Error::json(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({ "panic": message }),
+        )
+    });
+    let res = request(&app, "GET", "/");
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+    assert_eq!(br#"{"panic":"boom 42"}"#, res.body());
+}
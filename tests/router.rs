@@ -0,0 +1,307 @@
+use haro::{Application, Request, Response, UrlError};
+use http::{header::ALLOW, Method, StatusCode};
+
+mod common;
+
+use common::request;
+
+fn method(req: Request) -> Response {
+    Response::str(req.method())
+}
+
+#[test]
+fn test_method_routing() {
+    let mut app = Application::new("0:8080");
+    app.get("/users", method);
+    app.post("/users", method);
+    app.route_methods("/items", &[Method::PUT, Method::PATCH], method);
+    app.delete("/items", method);
+    app.route("/any", method);
+
+    assert_eq!(b"GET", request(&app, "get", "/users").body());
+    assert_eq!(b"POST", request(&app, "POST", "/users").body());
+    assert_eq!(b"PATCH", request(&app, "PATCH", "/items").body());
+    assert_eq!(b"DELETE", request(&app, "DELETE", "/items").body());
+    assert_eq!(b"PUT", request(&app, "PUT", "/any").body());
+
+    let res = request(&app, "DELETE", "/users");
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+    assert_eq!("GET, POST, HEAD, OPTIONS", res.headers()[ALLOW]);
+
+    let res = request(&app, "GET", "/items");
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+    assert_eq!("PUT, PATCH, DELETE, OPTIONS", res.headers()[ALLOW]);
+
+    let res = request(&app, "GET", "/missing");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+}
+
+#[test]
+fn test_head_and_options() {
+    let mut app = Application::new("0:8080");
+    app.get("/users", method);
+    app.route_methods("/items", &[Method::GET, Method::OPTIONS], method);
+
+    let res = request(&app, "HEAD", "/users");
+    assert_eq!(StatusCode::OK, res.status());
+    assert_eq!("4", res.headers()["content-length"]);
+    assert!(res.body().is_empty());
+
+    let res = request(&app, "OPTIONS", "/users");
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+    assert_eq!("GET, HEAD, OPTIONS", res.headers()[ALLOW]);
+    assert!(res.headers().get("content-length").is_none());
+
+    assert_eq!(b"OPTIONS", request(&app, "OPTIONS", "/items").body());
+}
+
+fn params(req: Request) -> Response {
+    let mut params: Vec<_> = req.params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    params.sort();
+    Response::str(params.join("&"))
+}
+
+#[test]
+fn test_params() {
+    let mut app = Application::new("0:8080");
+    app.route("/hello/:name", params);
+    app.route("/users/<id:int>", params);
+    app.route("/items/<uuid:uuid>", params);
+    app.route("/files/<rest:path>", params);
+    app.route("/years/<year:[0-9]{4}>/<slug:str>", params);
+    app.route("/docs/<name>.txt", params);
+
+    assert_eq!(b"name=a", request(&app, "GET", "/hello/a").body());
+    assert_eq!(b"id=42", request(&app, "GET", "/users/42").body());
+    let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    let expected = format!("uuid={uuid}");
+    let path = format!("/items/{uuid}");
+    assert_eq!(expected.as_bytes(), request(&app, "GET", &path).body());
+    assert_eq!(b"rest=a/b/c", request(&app, "GET", "/files/a/b/c").body());
+    assert_eq!(
+        b"slug=haro&year=2023",
+        request(&app, "GET", "/years/2023/haro").body()
+    );
+    assert_eq!(
+        b"name=readme",
+        request(&app, "GET", "/docs/readme.txt").body()
+    );
+
+    for path in [
+        "/x/hello/a",
+        "/hello/a/b/c",
+        "/users/abc",
+        "/items/not-a-uuid",
+        "/years/23/haro",
+        "/docs/readme.md",
+    ] {
+        let res = request(&app, "GET", path);
+        assert_eq!(StatusCode::NOT_FOUND, res.status(), "{path}");
+    }
+}
+
+#[test]
+fn test_typed_param() {
+    let mut app = Application::new("0:8080");
+    app.route("/users/<id:int>", |req: Request| {
+        match (req.param::<u8>("id"), req.param::<u8>("missing")) {
+            (Ok(id), Err(e)) => Response::str(format!("{id} {}", e.status())),
+            (Err(e), _) => e.into(),
+            _ => unreachable!(),
+        }
+    });
+
+    assert_eq!(
+        b"42 404 Not Found",
+        request(&app, "GET", "/users/42").body()
+    );
+    let res = request(&app, "GET", "/users/1000");
+    assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    assert_eq!(b"invalid value \"1000\" for path parameter id", res.body());
+}
+
+#[test]
+fn test_precedence() {
+    let mut app = Application::new("0:8080");
+    app.route("/files/<rest:path>", |_| Response::str("wildcard"));
+    app.route("/files/<name>", |_| Response::str("str"));
+    app.route("/files/<id:int>", |_| Response::str("int"));
+    app.route("/files/new", |_| Response::str("static"));
+    app.route("/files/<dir>/edit", |_| Response::str("edit"));
+    app.route("/files/new/edit/<p:path>/raw", params);
+    app.route("/files/<p:path>/raw", |_| Response::str("raw"));
+    app.route("/us", |_| Response::str("us"));
+    app.route("/user", |_| Response::str("user"));
+    app.route("/users", |_| Response::str("users"));
+
+    let cases = [
+        ("/files/new", "static"),
+        ("/files/42", "int"),
+        ("/files/readme", "str"),
+        ("/files/a/b", "wildcard"),
+        // the wildcard doesn't swallow the end of a longer route
+        ("/files/a/b/raw", "raw"),
+        ("/files/a/raw/b", "wildcard"),
+        // falls back from the static child to the parameter
+        ("/files/new/edit", "edit"),
+        ("/files/new/edit/a/b/raw", "p=a/b"),
+        ("/us", "us"),
+        ("/user", "user"),
+        ("/users", "users"),
+    ];
+    for (path, expected) in cases {
+        assert_eq!(
+            expected.as_bytes(),
+            request(&app, "GET", path).body(),
+            "{path}"
+        );
+    }
+    for path in ["/use", "/userss", "/files", "/files/"] {
+        let res = request(&app, "GET", path);
+        assert_eq!(StatusCode::NOT_FOUND, res.status(), "{path}");
+    }
+}
+
+#[test]
+fn test_url_for() {
+    let mut app = Application::new("0:8080");
+    app.get("/users/<id:int>", method).name("user");
+    app.get("/files/<path:path>", method).name("file");
+    app.get("/docs/<name>.txt", method).name("doc");
+    app.get("/", |req| match req.url_for("user", &[("id", "7")], &[]) {
+        Ok(url) => Response::str(url),
+        Err(e) => Response::str(e.to_string()),
+    })
+    .name("index");
+
+    assert_eq!("/", app.url_for("index", &[], &[]).unwrap());
+    assert_eq!(
+        "/users/42?tab=posts&q=a%20b",
+        app.url_for("user", &[("id", "42")], &[("tab", "posts"), ("q", "a b")])
+            .unwrap()
+    );
+    assert_eq!(
+        "/files/a%20b/c.txt",
+        app.url_for("file", &[("path", "a b/c.txt")], &[]).unwrap()
+    );
+    assert_eq!(
+        "/docs/read%20me.txt",
+        app.url_for("doc", &[("name", "read me")], &[]).unwrap()
+    );
+    assert_eq!(b"/users/7", request(&app, "GET", "/").body());
+
+    assert!(matches!(
+        app.url_for("missing", &[], &[]),
+        Err(UrlError::UnknownRoute(_))
+    ));
+    assert!(matches!(
+        app.url_for("user", &[], &[]),
+        Err(UrlError::MissingParam { .. })
+    ));
+    assert!(matches!(
+        app.url_for("user", &[("id", "abc")], &[]),
+        Err(UrlError::InvalidParam { .. })
+    ));
+}
+
+#[test]
+fn test_catch_all() {
+    let mut app = Application::new("0:8080");
+    app.get("/static/*path", |req| Response::str(&req.params["path"]))
+        .name("static");
+    app.get("/static/app.js", |_| Response::str("app"));
+    app.get("/api/<id:int>", |_| Response::str("api"));
+    app.get("/*", |req| Response::str(format!("spa {}", req.path())));
+
+    assert_eq!(
+        b"css/main.css",
+        request(&app, "GET", "/static/css/main.css").body()
+    );
+    assert_eq!(b"", request(&app, "GET", "/static/").body());
+    assert_eq!(b"app", request(&app, "GET", "/static/app.js").body());
+    assert_eq!(b"a b", request(&app, "GET", "/static/a%20b").body());
+    assert_eq!(b"api", request(&app, "GET", "/api/1").body());
+    assert_eq!(b"spa /api/x", request(&app, "GET", "/api/x").body());
+    assert_eq!(b"spa /", request(&app, "GET", "/").body());
+
+    assert_eq!(
+        "/static/css/a%20b.css",
+        app.url_for("static", &[("path", "css/a b.css")], &[])
+            .unwrap()
+    );
+}
+
+#[test]
+#[should_panic(expected = "must be the last segment")]
+fn test_catch_all_not_last() {
+    let mut app = Application::new("0:8080");
+    app.get("/files/*path/raw", method);
+}
+
+#[test]
+#[should_panic(expected = "unknown converter `integer`")]
+fn test_unknown_converter() {
+    let mut app = Application::new("0:8080");
+    app.get("/u/<id:integer>", method);
+}
+
+#[test]
+#[should_panic(expected = "unknown converter `integer`")]
+fn test_unknown_converter_in_segment() {
+    let mut app = Application::new("0:8080");
+    app.get("/u/<id:integer>.json", method);
+}
+
+#[test]
+fn test_not_found() {
+    let mut app = Application::new("0:8080");
+    app.get("/users", method);
+
+    let res = request(&app, "GET", "/missing");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+    assert_eq!(b"404 Not Found", res.body());
+
+    app.not_found(|req| {
+        Response::str(format!("no {}", req.path())).with_status(StatusCode::NOT_FOUND)
+    });
+    let res = request(&app, "GET", "/missing");
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+    assert_eq!(b"no /missing", res.body());
+
+    // a matching path with another method is still answered by 405
+    let res = request(&app, "POST", "/users");
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+}
+
+#[test]
+fn test_middlewares_applied_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    static WRAPPED: AtomicUsize = AtomicUsize::new(0);
+    fn tag(next: haro::DynHandler) -> haro::DynHandler {
+        WRAPPED.fetch_add(1, Ordering::SeqCst);
+        Arc::new(move |req: Request| next(req).header("x-tag", "1"))
+    }
+
+    let mut app = Application::new("0:8080");
+    app.middleware(tag);
+    app.get("/users", method);
+    let res = request(&app, "GET", "/users");
+    assert_eq!("1", res.headers()["x-tag"]);
+    let wrapped = WRAPPED.load(Ordering::SeqCst);
+    for uri in ["/users", "/missing", "/users"] {
+        request(&app, "GET", uri);
+    }
+    let res = request(&app, "DELETE", "/users");
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+    assert_eq!("1", res.headers()["x-tag"]);
+    assert_eq!(wrapped, WRAPPED.load(Ordering::SeqCst));
+
+    // routes added later are wrapped too
+    app.post("/users", method);
+    let res = request(&app, "POST", "/users");
+    assert_eq!(b"POST", res.body());
+    assert_eq!("1", res.headers()["x-tag"]);
+    assert!(WRAPPED.load(Ordering::SeqCst) > wrapped);
+}
@@ -0,0 +1,676 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use haro::{Application, Request, Response, ServerHandle, ShutdownHandle};
+
+fn connect(server: &ServerHandle) -> TcpStream {
+    TcpStream::connect(server.addr()).unwrap()
+}
+
+/// Address of an application started with `run`, once it is listening
+fn wait_for(handle: &ShutdownHandle) -> SocketAddr {
+    for _ in 0..50 {
+        if let Some(addr) = handle.addr() {
+            return addr;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("server did not start");
+}
+
+/// Read one response, returns the status line, lower-cased headers and body
+fn read_response<R: BufRead>(reader: &mut R) -> (String, Vec<(String, String)>, Vec<u8>) {
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        let (key, value) = line.split_once(':').unwrap();
+        let (key, value) = (key.trim().to_lowercase(), value.trim().to_string());
+        if key == "content-length" {
+            content_length = value.parse().unwrap();
+        }
+        headers.push((key, value));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    if header(&headers, "transfer-encoding") == Some("chunked") {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let size = usize::from_str_radix(line.trim(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+    (status.trim().to_string(), headers, body)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+fn echo_path(req: Request) -> Response {
+    Response::str(req.path())
+}
+
+#[test]
+fn test_keep_alive() {
+    let mut app = Application::new("127.0.0.1:0").keep_alive(Duration::from_millis(300));
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // sequential requests on the same connection
+    for path in ["/a", "/b"] {
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!("HTTP/1.1 200 OK", status);
+        assert_eq!(None, header(&headers, "connection"));
+        assert_eq!(path.as_bytes(), body);
+    }
+
+    // pipelined requests in a single write
+    stream
+        .write_all(b"GET /c HTTP/1.1\r\n\r\nGET /d HTTP/1.1\r\n\r\n")
+        .unwrap();
+    assert_eq!(b"/c".to_vec(), read_response(&mut reader).2);
+    assert_eq!(b"/d".to_vec(), read_response(&mut reader).2);
+
+    // idle connection is closed after the timeout
+    thread::sleep(Duration::from_millis(500));
+    let mut buf = Vec::new();
+    assert_eq!(0, reader.read_to_end(&mut buf).unwrap());
+}
+
+#[test]
+fn test_connection_close() {
+    let mut app = Application::new("127.0.0.1:0");
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    write!(stream, "GET /a HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let (_, headers, _) = read_response(&mut reader);
+    assert_eq!(Some("close"), header(&headers, "connection"));
+    let mut buf = Vec::new();
+    assert_eq!(0, reader.read_to_end(&mut buf).unwrap());
+
+    // HTTP/1.0 closes by default and keeps alive on request
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    write!(stream, "GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+    let (_, headers, _) = read_response(&mut reader);
+    assert_eq!(Some("keep-alive"), header(&headers, "connection"));
+    write!(stream, "GET /b HTTP/1.0\r\n\r\n").unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(Some("close"), header(&headers, "connection"));
+    assert_eq!(b"/b".to_vec(), body);
+    assert_eq!(0, reader.read_to_end(&mut buf).unwrap());
+}
+
+#[test]
+fn test_malformed_requests() {
+    let mut app = Application::new("127.0.0.1:0")
+        .max_uri_length(64)
+        .max_headers(256, 4)
+        .max_body_size(16);
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+
+    let cases: [(&[u8], &str); 9] = [
+        (b"garbage\r\n\r\n", "400 Bad Request"),
+        (b"GET / HTTP/1.1\r\nno colon\r\n\r\n", "400 Bad Request"),
+        (b"GET / HTTP/1.1\r\nBad Name: x\r\n\r\n", "400 Bad Request"),
+        (b"GET / HTTP/1.1\r\nHost : x\r\n\r\n", "400 Bad Request"),
+        // an obs-fold continuation line
+        (
+            b"POST / HTTP/1.1\r\nX-A: 1\r\n Transfer-Encoding: chunked\r\n\r\n",
+            "400 Bad Request",
+        ),
+        (
+            b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "400 Bad Request",
+        ),
+        (
+            b"POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n",
+            "413 Payload Too Large",
+        ),
+        (
+            b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n",
+            "431 Request Header Fields Too Large",
+        ),
+        (b"GET /\xff HTTP/1.1\r\n\r\n", "400 Bad Request"),
+    ];
+    for (request, expected) in cases {
+        let mut stream = connect(&server);
+        stream.write_all(request).unwrap();
+        let mut reader = BufReader::new(stream);
+        let (status, headers, _) = read_response(&mut reader);
+        assert_eq!(format!("HTTP/1.1 {expected}"), status);
+        assert_eq!(Some("close"), header(&headers, "connection"));
+    }
+
+    let long_uri = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(100));
+    let long_header = format!("GET / HTTP/1.1\r\nA: {}\r\n\r\n", "a".repeat(300));
+    for (request, expected) in [
+        (long_uri, "414 URI Too Long"),
+        (long_header, "431 Request Header Fields Too Large"),
+    ] {
+        let mut stream = connect(&server);
+        stream.write_all(request.as_bytes()).unwrap();
+        let (status, _, _) = read_response(&mut BufReader::new(stream));
+        assert_eq!(format!("HTTP/1.1 {expected}"), status);
+    }
+
+    // half-closed sockets don't take the workers down
+    for request in [
+        "",
+        "GET / HTTP/1.1\r\n",
+        "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc",
+    ] {
+        let mut stream = connect(&server);
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    let mut stream = connect(&server);
+    stream.write_all(b"GET /ok HTTP/1.1\r\n\r\n").unwrap();
+    let (status, _, body) = read_response(&mut BufReader::new(stream));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"/ok".to_vec(), body);
+}
+
+#[test]
+fn test_chunked() {
+    let mut app = Application::new("127.0.0.1:0");
+    app.route("/", |req: Request| {
+        let checksum = req.trailers().get("x-checksum").cloned();
+        let body = String::from_utf8_lossy(req.body()).to_string();
+        let res = Response::str(body).chunked();
+        match checksum {
+            Some(checksum) => res.header("x-checksum", checksum),
+            None => res,
+        }
+    });
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Checksum: abc\r\n\r\n",
+        )
+        .unwrap();
+    let (status, headers, body) = read_response(&mut reader);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(None, header(&headers, "content-length"));
+    assert_eq!(Some("abc"), header(&headers, "x-checksum"));
+    assert_eq!(b"Wikipedia".to_vec(), body);
+
+    // the connection is still usable after the chunked body
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nok")
+        .unwrap();
+    assert_eq!(b"ok".to_vec(), read_response(&mut reader).2);
+
+    // HTTP/1.0 clients get a content length
+    stream
+        .write_all(b"POST / HTTP/1.0\r\nContent-Length: 2\r\n\r\nok")
+        .unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(Some("2"), header(&headers, "content-length"));
+    assert_eq!(None, header(&headers, "transfer-encoding"));
+    assert_eq!(b"ok".to_vec(), body);
+
+    for (request, expected) in [
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+            "501 Not Implemented",
+        ),
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n",
+            "400 Bad Request",
+        ),
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            "400 Bad Request",
+        ),
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n+3\r\nabc\r\n0\r\n\r\n",
+            "400 Bad Request",
+        ),
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n 3 \r\nabc\r\n0\r\n\r\n",
+            "400 Bad Request",
+        ),
+        (
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\nffffffffffffffff\r\n",
+            "413 Payload Too Large",
+        ),
+    ] {
+        let mut stream = connect(&server);
+        stream.write_all(request.as_bytes()).unwrap();
+        let (status, _, _) = read_response(&mut BufReader::new(stream));
+        assert_eq!(format!("HTTP/1.1 {expected}"), status);
+    }
+}
+
+#[test]
+fn test_streaming() {
+    let mut app = Application::new("127.0.0.1:0");
+    app.route("/stream", |_| {
+        Response::stream("text/plain", std::io::repeat(b'a').take(100_000))
+    });
+    app.route("/chunks", |_| {
+        let rows = (0..3).map(|i| format!("{i},Haro\n").into_bytes());
+        Response::chunks("text/csv", rows)
+    });
+    app.route("/file", |_| Response::file("Cargo.toml").unwrap());
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    write!(stream, "GET /stream HTTP/1.1\r\n\r\n").unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(Some("chunked"), header(&headers, "transfer-encoding"));
+    assert_eq!(vec![b'a'; 100_000], body);
+
+    write!(stream, "GET /chunks HTTP/1.1\r\n\r\n").unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(Some("text/csv"), header(&headers, "content-type"));
+    assert_eq!(b"0,Haro\n1,Haro\n2,Haro\n".to_vec(), body);
+
+    write!(stream, "GET /file HTTP/1.1\r\n\r\n").unwrap();
+    let (_, headers, body) = read_response(&mut reader);
+    let expected = std::fs::read("Cargo.toml").unwrap();
+    assert_eq!(
+        Some("application/octet-stream"),
+        header(&headers, "content-type")
+    );
+    assert_eq!(expected, body);
+
+    // HTTP/1.0 clients read the stream until the connection is closed
+    write!(
+        stream,
+        "GET /chunks HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"
+    )
+    .unwrap();
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    let buf = String::from_utf8(buf).unwrap();
+    assert!(buf.contains("connection: close\r\n"));
+    assert!(buf.ends_with("\r\n\r\n0,Haro\n1,Haro\n2,Haro\n"));
+
+    let app = Application::new("127.0.0.1:0");
+    let res = app.request("get", "/", Default::default(), &[]);
+    assert_eq!(
+        b"404 Not Found".to_vec(),
+        res.into_body().into_bytes().unwrap()
+    );
+}
+
+#[test]
+fn test_binary_body() {
+    let png = vec![
+        0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0xff, 0x00,
+    ];
+    let body = png.clone();
+    let mut app = Application::new("127.0.0.1:0");
+    app.route("/", move |_| Response::bytes("image/png", body.clone()));
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    write!(stream, "GET / HTTP/1.1\r\n\r\n").unwrap();
+    let (status, headers, body) = read_response(&mut reader);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(Some("image/png"), header(&headers, "content-type"));
+    assert_eq!(Some("10"), header(&headers, "content-length"));
+    assert_eq!(png, body);
+
+    let res = Response::bytes("application/octet-stream", png);
+    assert!(res.to_string().starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn test_head() {
+    let mut app = Application::new("127.0.0.1:0");
+    app.get("/", |_| Response::str("Hello Haro"));
+    app.get("/stream", |_| {
+        Response::chunks("text/plain", vec![b"a".to_vec()])
+    });
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    write!(
+        stream,
+        "HEAD / HTTP/1.1\r\n\r\nHEAD /stream HTTP/1.1\r\n\r\n"
+    )
+    .unwrap();
+    write!(stream, "GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut status = String::new();
+    for _ in 0..2 {
+        status.clear();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!("HTTP/1.1 200 OK\r\n", status);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+    }
+    let (_, headers, body) = read_response(&mut reader);
+    assert_eq!(Some("10"), header(&headers, "content-length"));
+    assert_eq!(b"Hello Haro".to_vec(), body);
+}
+
+#[test]
+fn test_multipart_upload() {
+    let mut app = Application::new("127.0.0.1:0").upload_memory_limit(1024);
+    app.post("/upload", |req| {
+        let file = req.file("file").unwrap();
+        let spooled = file.path().is_some_and(|path| path.exists());
+        Response::str(format!(
+            "{} {} {} {spooled} {}",
+            req.data["name"],
+            file.filename(),
+            file.size(),
+            file.bytes().unwrap() == vec![b'x'; 4096],
+        ))
+    });
+    let server = app.spawn();
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let content = "x".repeat(4096);
+    let body = format!(
+        "--b\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nHaro\r\n\
+         --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"x.bin\"\r\n\r\n\
+         {content}\r\n--b--\r\n"
+    );
+    for _ in 0..2 {
+        write!(
+            stream,
+            "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let (status, _, body) = read_response(&mut reader);
+        assert_eq!("HTTP/1.1 200 OK", status);
+        assert_eq!(b"Haro x.bin 4096 true true", body.as_slice());
+    }
+
+    // a malformed body closes the connection
+    let body = "--b\r\nContent-Type: text/plain\r\n\r\nHaro\r\n--b--\r\n";
+    write!(
+        stream,
+        "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\n\
+         Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let (status, headers, _) = read_response(&mut reader);
+    assert_eq!("HTTP/1.1 400 Bad Request", status);
+    assert_eq!(Some("close"), header(&headers, "connection"));
+}
+
+#[test]
+fn test_panic_recovery() {
+    let mut app = Application::new("127.0.0.1:0").num_threads(1);
+    app.get("/panic", |_| -> Response { panic!("boom") });
+    app.get("/panic-body", |_| {
+        // panics while the response is written, after the handler returned
+        Response::chunks(
+            "text/plain",
+            (0..2).map(|i| match i {
+                0 => b"partial".to_vec(),
+                _ => panic!("broken stream"),
+            }),
+        )
+    });
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+
+    for _ in 0..3 {
+        let mut stream = connect(&server);
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        write!(stream, "GET /panic HTTP/1.1\r\n\r\n").unwrap();
+        let (status, headers, body) = read_response(&mut reader);
+        assert_eq!("HTTP/1.1 500 Internal Server Error", status);
+        assert_eq!(Some("close"), header(&headers, "connection"));
+        assert_eq!(b"500 Internal Server Error", body.as_slice());
+    }
+
+    // the only worker dies and is replaced
+    let mut stream = connect(&server);
+    write!(stream, "GET /panic-body HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = Vec::new();
+    let _ = stream.read_to_end(&mut buf);
+
+    let mut stream = connect(&server);
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    write!(stream, "GET /alive HTTP/1.1\r\n\r\n").unwrap();
+    let (status, _, body) = read_response(&mut reader);
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"/alive", body.as_slice());
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let mut app = Application::new("127.0.0.1:0").shutdown_timeout(Duration::from_millis(500));
+    app.get("/slow", |_| {
+        thread::sleep(Duration::from_millis(300));
+        Response::str("done")
+    });
+    app.route("/:name", echo_path);
+    let (hook_tx, hook_rx) = mpsc::channel();
+    let hook_tx = Mutex::new(hook_tx);
+    app.on_shutdown(move || hook_tx.lock().unwrap().send("hook").unwrap());
+    let handle = app.shutdown_handle();
+    let server = thread::spawn(move || app.run());
+    let addr = wait_for(&handle);
+    let mut idle = TcpStream::connect(addr).unwrap();
+
+    // an idle keep-alive connection
+    let mut idle_reader = BufReader::new(idle.try_clone().unwrap());
+    write!(idle, "GET /idle HTTP/1.1\r\n\r\n").unwrap();
+    read_response(&mut idle_reader);
+
+    let mut slow = TcpStream::connect(addr).unwrap();
+    write!(slow, "GET /slow HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    handle.shutdown();
+
+    // the request in flight is finished and its connection closed
+    let (status, headers, body) = read_response(&mut BufReader::new(slow));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(Some("close"), header(&headers, "connection"));
+    assert_eq!(b"done", body.as_slice());
+
+    server.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!("hook", hook_rx.try_recv().unwrap());
+    let mut buf = Vec::new();
+    assert_eq!(0, idle_reader.read_to_end(&mut buf).unwrap());
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_shutdown_timeout() {
+    let mut app = Application::new("127.0.0.1:0")
+        .num_threads(3)
+        .shutdown_timeout(Duration::from_millis(300));
+    app.get("/stuck", |_| {
+        thread::sleep(Duration::from_secs(4));
+        Response::str("late")
+    });
+    let handle = app.shutdown_handle();
+    let server = thread::spawn(move || app.run());
+    let addr = wait_for(&handle);
+    let mut streams: Vec<_> = (0..3).map(|_| TcpStream::connect(addr).unwrap()).collect();
+    // every worker is busy
+    for stream in &mut streams {
+        write!(stream, "GET /stuck HTTP/1.1\r\n\r\n").unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    handle.shutdown();
+    server.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_shutdown_before_run() {
+    let app = Application::new("127.0.0.1:0");
+    let handle = app.shutdown_handle();
+    handle.shutdown();
+
+    let start = Instant::now();
+    app.run();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_spawn() {
+    let mut app = Application::new("127.0.0.1:0");
+    app.route("/:name", echo_path);
+    let (hook_tx, hook_rx) = mpsc::channel();
+    let hook_tx = Mutex::new(hook_tx);
+    app.on_shutdown(move || hook_tx.lock().unwrap().send("hook").unwrap());
+    let server = app.spawn();
+    let addr = server.addr();
+    assert_ne!(0, addr.port());
+    assert_eq!(format!("http://{addr}/a"), server.url("/a"));
+
+    let mut stream = connect(&server);
+    write!(stream, "GET /a HTTP/1.1\r\n\r\n").unwrap();
+    let (status, _, body) = read_response(&mut BufReader::new(stream));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"/a", body.as_slice());
+
+    // dropping the handle stops the server
+    drop(server);
+    assert_eq!("hook", hook_rx.try_recv().unwrap());
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn test_queue_full() {
+    let mut app = Application::new("127.0.0.1:0")
+        .num_threads(1)
+        .queue_capacity(1)
+        .reject_when_busy(Duration::from_millis(1500));
+    app.get("/slow", |_| {
+        thread::sleep(Duration::from_millis(300));
+        Response::str("done")
+    });
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+
+    let mut busy = connect(&server);
+    write!(busy, "GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let mut queued = connect(&server);
+    write!(queued, "GET /queued HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    // the only worker is busy and the queue is full
+    let mut reader = BufReader::new(connect(&server));
+    let (status, headers, body) = read_response(&mut reader);
+    assert_eq!("HTTP/1.1 503 Service Unavailable", status);
+    assert_eq!(Some("2"), header(&headers, "retry-after"));
+    assert_eq!(Some("close"), header(&headers, "connection"));
+    assert_eq!(b"503 Service Unavailable", body.as_slice());
+    let mut buf = Vec::new();
+    assert_eq!(0, reader.read_to_end(&mut buf).unwrap());
+
+    let (status, _, body) = read_response(&mut BufReader::new(busy));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"done", body.as_slice());
+    let (status, _, body) = read_response(&mut BufReader::new(queued));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"/queued", body.as_slice());
+}
+
+#[test]
+fn test_timeouts() {
+    let mut app = Application::new("127.0.0.1:0")
+        .num_threads(1)
+        .header_timeout(Duration::from_millis(300))
+        .body_timeout(Duration::from_millis(300))
+        .write_timeout(Duration::from_millis(300));
+    app.post("/", |req: Request| {
+        Response::str(String::from_utf8_lossy(req.body()))
+    });
+    app.get("/stream", |_| {
+        Response::stream("text/plain", std::io::repeat(b'a').take(1 << 30))
+    });
+    app.route("/:name", echo_path);
+    let server = app.spawn();
+
+    // a client trickling its headers doesn't hold the worker past the header timeout
+    let start = Instant::now();
+    let mut stream = connect(&server);
+    write!(stream, "GET /a HTTP/1.1\r\n").unwrap();
+    for _ in 0..5 {
+        thread::sleep(Duration::from_millis(100));
+        if write!(stream, "X-Slow: 1\r\n").is_err() {
+            break;
+        }
+    }
+    let (status, headers, _) = read_response(&mut BufReader::new(stream));
+    assert_eq!("HTTP/1.1 408 Request Timeout", status);
+    assert_eq!(Some("close"), header(&headers, "connection"));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // a connection sending nothing
+    let (status, _, _) = read_response(&mut BufReader::new(connect(&server)));
+    assert_eq!("HTTP/1.1 408 Request Timeout", status);
+
+    // an incomplete body
+    let mut stream = connect(&server);
+    write!(stream, "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").unwrap();
+    let (status, _, _) = read_response(&mut BufReader::new(stream));
+    assert_eq!("HTTP/1.1 408 Request Timeout", status);
+
+    // a client not reading the response is dropped after the write timeout
+    let mut stuck = connect(&server);
+    write!(stuck, "GET /stream HTTP/1.1\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // the worker is still available
+    let mut stream = connect(&server);
+    write!(stream, "POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nok").unwrap();
+    let (status, _, body) = read_response(&mut BufReader::new(stream));
+    assert_eq!("HTTP/1.1 200 OK", status);
+    assert_eq!(b"ok", body.as_slice());
+    drop(stuck);
+}
@@ -0,0 +1,76 @@
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use haro::{Application, DynHandler, Request, Response};
+
+mod common;
+
+use common::request;
+
+struct Config {
+    greeting: &'static str,
+}
+
+fn hello(req: Request) -> Response {
+    let config = req.state::<Config>().unwrap();
+    Response::str(format!(
+        "{} {}",
+        config.greeting,
+        req.param::<String>("name").unwrap()
+    ))
+}
+
+fn count(next: DynHandler) -> DynHandler {
+    Arc::new(move |req: Request| -> Response {
+        req.state::<AtomicUsize>()
+            .unwrap()
+            .fetch_add(1, Ordering::SeqCst);
+        next(req)
+    })
+}
+
+#[test]
+fn test_state() {
+    let mut app = Application::new("0:8080");
+    app.state(Config { greeting: "Hi" });
+    app.state(AtomicUsize::new(0));
+    app.middleware(count);
+    app.route("/hello/:name", hello);
+    app.route("/missing", |req: Request| {
+        Response::str(req.state::<String>().is_none().to_string())
+    });
+
+    assert_eq!(b"Hi Haro", request(&app, "GET", "/hello/Haro").body());
+    assert_eq!(b"true", request(&app, "GET", "/missing").body());
+
+    // a value of the same type replaces the previous one
+    app.state(Config { greeting: "Hello" });
+    assert_eq!(b"Hello Haro", request(&app, "GET", "/hello/Haro").body());
+
+    // the state is shared with the connections of a running server
+    app.route("/count", |req: Request| {
+        let hits = req.state::<AtomicUsize>().unwrap();
+        Response::str(hits.load(Ordering::SeqCst).to_string())
+    });
+    let server = app.spawn();
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    write!(stream, "GET /hello/Ray HTTP/1.1\r\n\r\n").unwrap();
+    write!(stream, "GET /count HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let mut res = String::new();
+    BufReader::new(stream).read_to_string(&mut res).unwrap();
+    assert!(res.contains("\r\n\r\nHello Ray"));
+    assert!(res.ends_with("\r\n\r\n5"));
+}
+
+#[test]
+fn test_state_type_is_exact() {
+    let mut app = Application::new("0:8080");
+    app.state(Arc::new(1u32));
+    app.route("/", |req: Request| {
+        let nested = req.state::<Arc<u32>>().map(|v| **v);
+        Response::str(format!("{:?} {:?}", nested, req.state::<u32>()))
+    });
+    assert_eq!(b"Some(1) None", request(&app, "GET", "/").body());
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use haro::{Application, Response, ServerHandle};
+use tera::Context;
+
+fn app(pattern: &str) -> Application {
+    let mut app = Application::new("0:8080");
+    app.get(pattern, |_| Response::str("user")).name("user");
+    app.get("/", |_| Response::tmpl("links.html", Context::new()));
+    app
+}
+
+fn links(app: &Application) -> String {
+    let res = app.request("GET", "/", HashMap::new(), &[]);
+    String::from_utf8_lossy(res.body()).to_string()
+}
+
+/// Response to a request for the links of a running application
+fn get(server: &ServerHandle) -> String {
+    let mut stream = TcpStream::connect(server.addr()).unwrap();
+    write!(stream, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    res
+}
+
+#[test]
+fn test_url_for() {
+    let app = app("/users/<id:int>");
+    assert_eq!("<a href=\"/users/42?tab=posts\">user</a>\n", links(&app));
+}
+
+#[test]
+fn test_url_for_several_apps() {
+    let users = app("/users/<id:int>");
+    let members = app("/members/<id:int>");
+    let server = members.spawn();
+
+    // each application renders against its own routes, even when they run side by side
+    for _ in 0..3 {
+        assert_eq!("<a href=\"/users/42?tab=posts\">user</a>\n", links(&users));
+        assert!(get(&server).ends_with("<a href=\"/members/42?tab=posts\">user</a>\n"));
+    }
+}